@@ -0,0 +1,151 @@
+//! Show or change the cpu affinity, scheduling policy and nice value of a process, like
+//! `taskset`, `chrt` and `renice` combined.
+//!
+//!     scheduler-ctl [--pid N] [--cpus 0-3] [--policy rr] [--rt-priority 10] [--nice -5]
+//!                   [--all-threads]
+//!
+//! Without any setting, the current values are shown. Otherwise the previous and the new values
+//! are shown. The process defaults to scheduler-ctl itself. Run with
+//! `cargo run --example scheduler-ctl -- --pid N ...`.
+extern crate scheduler;
+
+use std::env;
+use std::process;
+use scheduler::{get_affinity_auto, get_policy_and_priority, get_priority, set_affinity,
+                set_affinity_all_threads, set_nice_all_threads, set_policy, set_priority,
+                set_process_policy, CpuSet, Error, Pid, Policy, ThreadReport, Tid, Which};
+
+const USAGE: &str = "usage: scheduler-ctl [--pid N] [--cpus LIST] [--policy POLICY] \
+                     [--rt-priority N] [--nice N] [--all-threads]\n\
+                     policies: other, batch, idle, fifo, rr";
+
+/// The command line arguments.
+#[derive(Default)]
+struct Args {
+    pid: Option<Pid>,
+    cpus: Option<CpuSet>,
+    policy: Option<Policy>,
+    rt_priority: Option<i32>,
+    nice: Option<i32>,
+    all_threads: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut arguments = env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        if argument == "--all-threads" {
+            args.all_threads = true;
+            continue;
+        }
+        let value = match arguments.next() {
+            Some(value) => value,
+            None => return Err(format!("missing value for {}", argument)),
+        };
+        let invalid = |error: &dyn ToString| {
+            format!("invalid value {:?} for {}: {}", value, argument, error.to_string())
+        };
+        match argument.as_str() {
+            "--pid" => args.pid = Some(Pid::from_raw(value.parse().map_err(|e| invalid(&e))?)),
+            "--cpus" => args.cpus = Some(value.parse().map_err(|e| invalid(&e))?),
+            "--policy" => args.policy = Some(value.parse().map_err(|e| invalid(&e))?),
+            "--rt-priority" => args.rt_priority = Some(value.parse().map_err(|e| invalid(&e))?),
+            "--nice" => args.nice = Some(value.parse().map_err(|e| invalid(&e))?),
+            _ => return Err(format!("unknown argument {}", argument)),
+        }
+    }
+    if args.rt_priority.is_some() && args.policy.is_none() {
+        return Err("--rt-priority needs --policy".to_string());
+    }
+    Ok(args)
+}
+
+/// The settings of a process, as shown.
+struct Settings {
+    affinity: CpuSet,
+    policy: (Policy, i32),
+    nice: i32,
+}
+
+fn current(pid: Pid) -> Result<Settings, Error> {
+    Ok(Settings {
+        affinity: get_affinity_auto(pid)?,
+        policy: get_policy_and_priority(pid)?,
+        nice: get_priority(Which::Process, pid.as_raw())?,
+    })
+}
+
+fn show(label: &str, pid: Pid, settings: &Settings) {
+    println!("pid {}'s {} affinity: {}", pid.as_raw(), label, settings.affinity);
+    println!("pid {}'s {} policy: {}", pid.as_raw(), label, settings.policy.0);
+    println!("pid {}'s {} priority: {}", pid.as_raw(), label, settings.policy.1);
+    println!("pid {}'s {} nice value: {}", pid.as_raw(), label, settings.nice);
+}
+
+/// Fail with the first thread that failed, and show the threads that exited meanwhile.
+fn check_threads(setting: &str, report: ThreadReport) -> Result<(), Error> {
+    for tid in report.vanished {
+        println!("thread {} exited before its {} was set", tid.as_raw(), setting);
+    }
+    first_failure(report.failed)
+}
+
+fn first_failure(failures: Vec<(Tid, Error)>) -> Result<(), Error> {
+    match failures.into_iter().next() {
+        Some((_, error)) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn apply(pid: Pid, args: &Args) -> Result<(), Error> {
+    if let Some(ref cpus) = args.cpus {
+        if args.all_threads {
+            check_threads("affinity", set_affinity_all_threads(pid, cpus)?)?;
+        } else {
+            set_affinity(pid, cpus)?;
+        }
+    }
+    if let Some(policy) = args.policy {
+        let priority = args.rt_priority.unwrap_or(0);
+        if args.all_threads {
+            first_failure(set_process_policy(pid, policy, priority)?)?;
+        } else {
+            set_policy(pid, policy, priority)?;
+        }
+    }
+    if let Some(nice) = args.nice {
+        if args.all_threads {
+            check_threads("nice value", set_nice_all_threads(pid, nice)?)?;
+        } else {
+            set_priority(Which::Process, pid.as_raw(), nice)?;
+        }
+    }
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<(), Error> {
+    let pid = args.pid.unwrap_or_else(Pid::this);
+    let previous = current(pid)?;
+    if args.cpus.is_none() && args.policy.is_none() && args.nice.is_none() {
+        show("current", pid, &previous);
+        return Ok(());
+    }
+    show("previous", pid, &previous);
+    apply(pid, args)?;
+    show("new", pid, &current(pid)?);
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("scheduler-ctl: {}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(&args) {
+        eprintln!("scheduler-ctl: {}", error);
+        process::exit(1);
+    }
+}
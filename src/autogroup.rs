@@ -0,0 +1,149 @@
+//! Autogroups, which share the CPU between sessions before sharing it between their processes
+//!
+//! With autogrouping, the nice value of a process only weighs it against the processes of its
+//! session, the nice value of the autogroup weighs the session against the others.
+use libc::ESRCH;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use error::Error;
+use procfs::proc_dir;
+use target::Pid;
+
+/// Autogroup of a process, see `get_autogroup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AutogroupInfo {
+    /// The identifier of the autogroup
+    pub id: u64,
+    /// The nice value of the autogroup
+    pub nice: i32,
+}
+
+/// Get the autogroup of a process, read from `/proc/<pid>/autogroup`.
+///
+/// Returns `None` if the kernel was built without `CONFIG_SCHED_AUTOGROUP` or autogrouping is
+/// disabled in `/proc/sys/kernel/sched_autogroup_enabled`.
+pub fn get_autogroup(pid: Pid) -> Result<Option<AutogroupInfo>, Error> {
+    match fs::read_to_string(autogroup_file(pid)) {
+        Ok(_) if !autogroup_enabled() => Ok(None),
+        Ok(autogroup) => parse_autogroup(&autogroup).map(Some),
+        Err(error) => missing_autogroup(pid, error).map(|()| None),
+    }
+}
+
+/// Set the nice value of the autogroup of a process, in the range `-20..=19`, by writing
+/// `/proc/<pid>/autogroup`. This changes the weight of every process in the autogroup.
+///
+/// Lowering the nice value requires `CAP_SYS_NICE` or a sufficient `RLIMIT_NICE`. Fails with
+/// `ErrorKind::Unsupported` if autogrouping is not available, see `get_autogroup`.
+pub fn set_autogroup_nice(pid: Pid, nice: i32) -> Result<(), Error> {
+    if !autogroup_enabled() {
+        return Err(not_enabled());
+    }
+    match fs::write(autogroup_file(pid), nice.to_string()) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            missing_autogroup(pid, error)?;
+            Err(not_enabled())
+        }
+    }
+}
+
+fn autogroup_file(pid: Pid) -> PathBuf {
+    proc_dir(pid.into()).join("autogroup")
+}
+
+/// Check `/proc/sys/kernel/sched_autogroup_enabled`, which is missing without
+/// `CONFIG_SCHED_AUTOGROUP`.
+fn autogroup_enabled() -> bool {
+    match fs::read_to_string("/proc/sys/kernel/sched_autogroup_enabled") {
+        Ok(enabled) => enabled.trim() != "0",
+        Err(_) => false,
+    }
+}
+
+/// Tell apart a missing autogroup file from a missing process, which returns an error.
+fn missing_autogroup(pid: Pid, error: io::Error) -> Result<(), Error> {
+    match error.kind() {
+        io::ErrorKind::NotFound if proc_dir(pid.into()).exists() => Ok(()),
+        io::ErrorKind::NotFound => Err(Error::from_raw_os_error(ESRCH)),
+        _ => Err(error.into()),
+    }
+}
+
+fn not_enabled() -> Error {
+    let message = "autogrouping is not enabled in this kernel";
+    Error::Io(io::Error::new(io::ErrorKind::Unsupported, message))
+}
+
+/// Parse a `/proc/<pid>/autogroup` file, e.g. `/autogroup-198 nice 0`.
+fn parse_autogroup(autogroup: &str) -> Result<AutogroupInfo, Error> {
+    let fields: Vec<&str> = autogroup.split_whitespace().collect();
+    let info = match fields[..] {
+        [name, "nice", nice] => name.strip_prefix("/autogroup-")
+            .and_then(|id| id.parse().ok())
+            .and_then(|id| nice.parse().ok().map(|nice| AutogroupInfo { id, nice })),
+        _ => None,
+    };
+    info.ok_or_else(|| {
+        let message = format!("invalid autogroup {:?}", autogroup);
+        Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::setsid;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+    use super::{autogroup_enabled, get_autogroup, parse_autogroup, set_autogroup_nice,
+                AutogroupInfo};
+    use error::ErrorKind;
+    use target::Pid;
+
+    #[test]
+    fn test_parse_autogroup() {
+        assert_eq!(AutogroupInfo { id: 198, nice: 0 },
+                   parse_autogroup("/autogroup-198 nice 0\n").unwrap());
+        assert_eq!(AutogroupInfo { id: 7, nice: -20 },
+                   parse_autogroup("/autogroup-7 nice -20").unwrap());
+        for &invalid in &["", "/autogroup-x nice 0", "/autogroup-1 prio 0", "/autogroup-1 nice",
+                          "/group-1 nice 0", "/autogroup-1 nice 0 1"] {
+            assert_eq!(ErrorKind::Other, parse_autogroup(invalid).unwrap_err().kind());
+        }
+    }
+
+    #[test]
+    fn test_set_autogroup_nice() {
+        let mut command = Command::new("sleep");
+        command.arg("1");
+        // A new session gets a new autogroup, leaving the one of the tests alone
+        unsafe {
+            command.pre_exec(|| {
+                setsid();
+                Ok(())
+            });
+        }
+        let mut child = command.spawn().unwrap();
+        let pid = Pid::from(&child);
+        let before = get_autogroup(pid);
+        let result = set_autogroup_nice(pid, 5);
+        let after = get_autogroup(pid);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        if !autogroup_enabled() {
+            assert_eq!(None, before.unwrap());
+            assert_eq!(ErrorKind::Unsupported, result.unwrap_err().kind());
+            return;
+        }
+        result.unwrap();
+        assert_eq!(0, before.unwrap().unwrap().nice);
+        assert_eq!(5, after.unwrap().unwrap().nice);
+    }
+
+    #[test]
+    fn test_get_autogroup_missing_process() {
+        let error = get_autogroup(Pid::from_raw(i32::MAX)).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+}
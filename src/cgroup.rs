@@ -0,0 +1,222 @@
+//! The cpu and cpuset controllers of cgroup v2, which limit the CPUs of a group of processes
+//!
+//! On systems that use cgroups, the CPU time a service gets is decided by the weight and limit
+//! of its cgroup before the nice values of its threads. Enabled with the `cgroup` feature.
+//! Changing a cgroup requires write access to its files, which the owner of a cgroup tree can
+//! delegate, otherwise the setters fail with `ErrorKind::PermissionDenied`.
+use libc::{EACCES, EROFS};
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use cpuset::CpuSet;
+use error::Error;
+
+/// Mount point of the cgroup v2 hierarchy when it is not found in `/proc/self/mountinfo`.
+const DEFAULT_MOUNT: &str = "/sys/fs/cgroup";
+
+/// The range of `cpu.weight`, where 100 is the default.
+const WEIGHT_RANGE: RangeInclusive<u32> = 1..=10_000;
+
+/// A cgroup v2 directory, see `Cgroup::current`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Get the cgroup of the calling process, the `0::` line of `/proc/self/cgroup` under the
+    /// cgroup v2 mount point in `/proc/self/mountinfo`.
+    ///
+    /// Fails with `ErrorKind::Unsupported` if the system only uses cgroup v1.
+    pub fn current() -> Result<Cgroup, Error> {
+        let cgroup = fs::read_to_string("/proc/self/cgroup")?;
+        let path = parse_cgroup(&cgroup)
+            .ok_or_else(|| unsupported("no cgroup v2 hierarchy in /proc/self/cgroup"))?;
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+        let mount = parse_mount_point(&mountinfo).unwrap_or_else(|| PathBuf::from(DEFAULT_MOUNT));
+        Ok(Cgroup::from_path(mount.join(path.trim_start_matches('/'))))
+    }
+
+    /// Use the cgroup in the directory `path`, e.g. `/sys/fs/cgroup/system.slice`.
+    pub fn from_path<P: Into<PathBuf>>(path: P) -> Cgroup {
+        Cgroup { path: path.into() }
+    }
+
+    /// Get the directory of the cgroup.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the weight of the cgroup against its siblings, read from `cpu.weight`.
+    pub fn cpu_weight(&self) -> Result<u32, Error> {
+        let weight = self.read("cpu.weight")?;
+        weight.trim().parse().map_err(|_| invalid("cpu.weight", &weight))
+    }
+
+    /// Set the weight of the cgroup against its siblings, in the range `1..=10000` where 100 is
+    /// the default, by writing `cpu.weight`.
+    pub fn set_cpu_weight(&self, weight: u32) -> Result<(), Error> {
+        if !WEIGHT_RANGE.contains(&weight) {
+            return Err(Error::InvalidArgument(format!("cpu.weight {} is not in {:?}",
+                                                      weight, WEIGHT_RANGE)));
+        }
+        self.write("cpu.weight", &weight.to_string())
+    }
+
+    /// Get the bandwidth limit of the cgroup, as `(quota, period)`: in every period, its
+    /// processes run at most for the quota, summed over all CPUs. Read from `cpu.max`.
+    ///
+    /// Returns `None` if the cgroup is not limited.
+    pub fn cpu_max(&self) -> Result<Option<(Duration, Duration)>, Error> {
+        let max = self.read("cpu.max")?;
+        parse_cpu_max(&max).ok_or_else(|| invalid("cpu.max", &max))
+    }
+
+    /// Set the bandwidth limit of the cgroup, see `cpu_max`, where `None` removes the limit
+    /// and keeps the period. Written to `cpu.max`.
+    pub fn set_cpu_max(&self, max: Option<(Duration, Duration)>) -> Result<(), Error> {
+        match max {
+            Some((quota, period)) => {
+                self.write("cpu.max", &format!("{} {}", quota.as_micros(), period.as_micros()))
+            }
+            None => self.write("cpu.max", "max"),
+        }
+    }
+
+    /// Get the CPUs the processes of the cgroup may run on, read from `cpuset.cpus.effective`.
+    ///
+    /// These are the CPUs granted by the cgroup and its ancestors, the affinity of a thread can
+    /// only select from them.
+    pub fn effective_cpus(&self) -> Result<CpuSet, Error> {
+        Ok(self.read("cpuset.cpus.effective")?.trim().parse()?)
+    }
+
+    fn read(&self, file: &str) -> Result<String, Error> {
+        Ok(fs::read_to_string(self.path.join(file))?)
+    }
+
+    /// Write a file of the cgroup, telling apart a cgroup that was not delegated.
+    fn write(&self, file: &str, value: &str) -> Result<(), Error> {
+        let path = self.path.join(file);
+        fs::write(&path, value).map_err(|error| match error.raw_os_error() {
+            Some(EACCES) | Some(EROFS) => {
+                let message = format!("writing {} is not allowed, the cgroup needs to be \
+                                       delegated: {}", path.display(), error);
+                Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, message))
+            }
+            _ => error.into(),
+        })
+    }
+}
+
+/// Parse the path of the cgroup v2 hierarchy from a `/proc/<pid>/cgroup` file.
+fn parse_cgroup(cgroup: &str) -> Option<&str> {
+    cgroup.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Parse the mount point of the cgroup v2 hierarchy from a `/proc/<pid>/mountinfo` file, where
+/// the file system type follows the ` - ` separator.
+fn parse_mount_point(mountinfo: &str) -> Option<PathBuf> {
+    mountinfo.lines().find_map(|line| {
+        let (mount, fs_type) = line.split_once(" - ")?;
+        match fs_type.split_whitespace().next() {
+            Some("cgroup2") => mount.split_whitespace().nth(4).map(PathBuf::from),
+            _ => None,
+        }
+    })
+}
+
+/// Parse a `cpu.max` file in microseconds, e.g. `max 100000` or `50000 100000`.
+fn parse_cpu_max(max: &str) -> Option<Option<(Duration, Duration)>> {
+    let fields: Vec<&str> = max.split_whitespace().collect();
+    match fields[..] {
+        [quota, period] => {
+            let period = Duration::from_micros(period.parse().ok()?);
+            match quota {
+                "max" => Some(None),
+                quota => Some(Some((Duration::from_micros(quota.parse().ok()?), period))),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn invalid(file: &str, contents: &str) -> Error {
+    let message = format!("invalid {} {:?}", file, contents);
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn unsupported(message: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Unsupported, message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use std::time::Duration;
+    use super::{parse_cgroup, parse_cpu_max, parse_mount_point, Cgroup};
+    use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+
+    #[test]
+    fn test_parse_cgroup() {
+        let cgroup = "4:memory:/user.slice\n1:cpu:/\n0::/user.slice/session-2.scope\n";
+        assert_eq!(Some("/user.slice/session-2.scope"), parse_cgroup(cgroup));
+        assert_eq!(None, parse_cgroup("4:memory:/user.slice\n"));
+        let mountinfo = "32 24 0:28 / /sys/fs/cgroup rw,relatime - tmpfs tmpfs rw,mode=755\n\
+                         42 32 0:38 / /sys/fs/cgroup/unified rw,relatime - cgroup2 cgroup2 rw\n";
+        assert_eq!(Some(PathBuf::from("/sys/fs/cgroup/unified")), parse_mount_point(mountinfo));
+        let mountinfo = "29 23 0:26 / /sys/fs/cgroup rw shared:4 - cgroup2 cgroup2 rw,nsdelegate";
+        assert_eq!(Some(PathBuf::from("/sys/fs/cgroup")), parse_mount_point(mountinfo));
+        assert_eq!(None, parse_mount_point("33 32 0:29 / /sys/fs/cgroup/cpu rw - cgroup cgroup"));
+    }
+
+    #[test]
+    fn test_parse_cpu_max() {
+        assert_eq!(Some(None), parse_cpu_max("max 100000\n"));
+        assert_eq!(Some(Some((Duration::from_millis(50), Duration::from_millis(100)))),
+                   parse_cpu_max("50000 100000\n"));
+        assert_eq!(None, parse_cpu_max("max"));
+        assert_eq!(None, parse_cpu_max("x 100000"));
+    }
+
+    #[test]
+    fn test_current_cgroup() {
+        match Cgroup::current() {
+            Ok(cgroup) => assert!(cgroup.path().exists()),
+            Err(error) => assert_eq!(ErrorKind::Unsupported, error.kind()),
+        }
+    }
+
+    #[test]
+    fn test_cgroup_files() {
+        let dir = env::temp_dir().join(format!("scheduler-cgroup-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |file: &str, contents: &str| fs::write(dir.join(file), contents).unwrap();
+        write("cpu.weight", "100\n");
+        write("cpu.max", "max 100000\n");
+        write("cpuset.cpus.effective", "0-3,8\n");
+        let cgroup = Cgroup::from_path(&dir);
+        assert_eq!(100, cgroup.cpu_weight().unwrap());
+        cgroup.set_cpu_weight(250).unwrap();
+        assert_eq!(250, cgroup.cpu_weight().unwrap());
+        assert_eq!(None, cgroup.cpu_max().unwrap());
+        let max = (Duration::from_millis(20), Duration::from_millis(100));
+        cgroup.set_cpu_max(Some(max)).unwrap();
+        assert_eq!(Some(max), cgroup.cpu_max().unwrap());
+        let expected: CpuSet = "0-3,8".parse().unwrap();
+        assert_eq!(expected, cgroup.effective_cpus().unwrap());
+        match cgroup.set_cpu_weight(0) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        let missing = Cgroup::from_path(Path::new("/proc/self/missing-cgroup"));
+        assert_eq!(ErrorKind::Other, missing.cpu_weight().unwrap_err().kind());
+    }
+}
@@ -0,0 +1,107 @@
+//! Start child processes with their scheduling set up before they run
+use libc::{cpu_set_t, sched_param, sched_setaffinity, sched_setscheduler, setpriority, EINVAL,
+           PRIO_PROCESS};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use cpuset::CpuSet;
+use sched::{validate_priority, Policy};
+
+/// Scheduling settings for `std::process::Command`, applied by the child before it runs the
+/// program.
+///
+/// Applying the settings after `spawn` races with the start of the child, these take effect
+/// before its first instruction. Each method installs a `pre_exec` hook, so the settings are
+/// applied in the order of the calls, after the hooks installed before. If one fails, the
+/// program is not run and `spawn` returns the error, keeping the raw OS error code.
+pub trait CommandSchedExt {
+    /// Set the nice value of the child, used by `Policy::Other` and `Policy::Batch`.
+    fn nice(&mut self, nice: i32) -> &mut Command;
+
+    /// Set the scheduling policy and priority of the child, checked like in `set_policy`.
+    fn policy(&mut self, policy: Policy, priority: i32) -> &mut Command;
+
+    /// Set the cpu affinity of the child.
+    fn cpu_affinity(&mut self, cpuset: CpuSet) -> &mut Command;
+}
+
+impl CommandSchedExt for Command {
+    fn nice(&mut self, nice: i32) -> &mut Command {
+        // Only async-signal-safe calls are allowed between fork and exec, the errors are raw
+        // OS error codes that need no allocation
+        unsafe {
+            self.pre_exec(move || match setpriority(PRIO_PROCESS as _, 0, nice) {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            })
+        }
+    }
+
+    fn policy(&mut self, policy: Policy, priority: i32) -> &mut Command {
+        let valid = validate_priority(policy, priority).is_ok();
+        let params = sched_param { sched_priority: priority };
+        let c_policy = policy.to_raw();
+        unsafe {
+            self.pre_exec(move || {
+                if !valid {
+                    return Err(io::Error::from_raw_os_error(EINVAL));
+                }
+                match sched_setscheduler(0, c_policy, &params) {
+                    0 => Ok(()),
+                    _ => Err(io::Error::last_os_error()),
+                }
+            })
+        }
+    }
+
+    fn cpu_affinity(&mut self, cpuset: CpuSet) -> &mut Command {
+        // The mask is allocated here and moved into the hook, the child only reads it
+        unsafe {
+            self.pre_exec(move || {
+                let mask = cpuset.mask_ptr() as *const cpu_set_t;
+                match sched_setaffinity(0, cpuset.byte_size(), mask) {
+                    0 => Ok(()),
+                    _ => Err(io::Error::last_os_error()),
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::EINVAL;
+    use std::process::Command;
+    use super::CommandSchedExt;
+    use cpuset::CpuSet;
+    use procfs::proc_sched_info;
+    use sched::{get_affinity_auto, Policy};
+    use target::Pid;
+
+    #[test]
+    fn test_command_sched_ext() {
+        let mut child = Command::new("sleep").arg("1")
+            .cpu_affinity(CpuSet::single(0))
+            .policy(Policy::Batch, 0)
+            .nice(7)
+            .spawn()
+            .unwrap();
+        let pid = Pid::from(&child);
+        let info = proc_sched_info(pid);
+        let affinity = get_affinity_auto(pid);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let info = info.unwrap();
+        assert_eq!(Policy::Batch, info.policy);
+        assert_eq!(7, info.nice);
+        assert_eq!(CpuSet::single(0), affinity.unwrap());
+    }
+
+    #[test]
+    fn test_command_sched_ext_error() {
+        let error = Command::new("true").policy(Policy::Fifo, 0).spawn().unwrap_err();
+        assert_eq!(Some(EINVAL), error.raw_os_error());
+        let error = Command::new("true").cpu_affinity(CpuSet::single(1000)).spawn().unwrap_err();
+        assert_eq!(Some(EINVAL), error.raw_os_error());
+    }
+}
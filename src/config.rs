@@ -0,0 +1,325 @@
+//! Scheduling settings applied together, rolling back on failure
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::fmt;
+use cpuset::CpuSet;
+use error::Error;
+use resource::{get_priority, set_priority, Which};
+use sched::{get_affinity_auto, get_policy_and_priority, set_affinity, set_policy_unchecked,
+            validate_priority, Policy};
+use target::Target;
+
+/// A setting of a `SchedulerConfig`, in the order they are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigStep {
+    /// The cpu affinity
+    Affinity,
+    /// The scheduling policy and priority
+    Policy,
+    /// The nice value
+    Nice,
+}
+
+impl fmt::Display for ConfigStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ConfigStep::Affinity => "cpu affinity",
+            ConfigStep::Policy => "scheduling policy",
+            ConfigStep::Nice => "nice value",
+        })
+    }
+}
+
+const STEPS: [ConfigStep; 3] = [ConfigStep::Affinity, ConfigStep::Policy, ConfigStep::Nice];
+
+/// Scheduling settings of a thread, applied together with `apply_to`.
+///
+/// Settings that are not set are left unchanged. Compare with `current_for` to find the settings
+/// of a thread that differ.
+///
+/// With the `serde` feature, serializes as a map of the settings that are set, e.g.
+/// `{"affinity": "0-3", "policy": "fifo", "priority": 10, "nice": -5}`. A missing priority is 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ConfigFields", into = "ConfigFields"))]
+pub struct SchedulerConfig {
+    affinity: Option<CpuSet>,
+    policy: Option<(Policy, i32)>,
+    nice: Option<i32>,
+}
+
+impl SchedulerConfig {
+    /// Create a `SchedulerConfig` that changes nothing.
+    pub fn new() -> SchedulerConfig {
+        SchedulerConfig::default()
+    }
+
+    /// Get the settings of a thread, with every setting set.
+    ///
+    /// The affinity is sized like in `get_affinity_auto`. The deadline parameters of
+    /// `Policy::Deadline` are not included, see `get_sched_attr`.
+    pub fn current_for<T: Into<Target>>(target: T) -> Result<SchedulerConfig, Error> {
+        let target = target.into();
+        Ok(SchedulerConfig {
+            affinity: Some(get_affinity_auto(target)?),
+            policy: Some(get_policy_and_priority(target)?),
+            nice: Some(get_priority(Which::Process, target.as_raw())?),
+        })
+    }
+
+    /// Set the cpu affinity.
+    pub fn affinity(mut self, cpuset: CpuSet) -> SchedulerConfig {
+        self.affinity = Some(cpuset);
+        self
+    }
+
+    /// Set the scheduling policy and priority, checked like in `set_policy`.
+    pub fn policy(mut self, policy: Policy, priority: i32) -> SchedulerConfig {
+        self.policy = Some((policy, priority));
+        self
+    }
+
+    /// Set the nice value, used by `Policy::Other` and `Policy::Batch`.
+    pub fn nice(mut self, nice: i32) -> SchedulerConfig {
+        self.nice = Some(nice);
+        self
+    }
+
+    /// Get the cpu affinity, if set.
+    pub fn get_affinity(&self) -> Option<&CpuSet> {
+        self.affinity.as_ref()
+    }
+
+    /// Get the scheduling policy and priority, if set.
+    pub fn get_policy(&self) -> Option<(Policy, i32)> {
+        self.policy
+    }
+
+    /// Get the nice value, if set.
+    pub fn get_nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    /// Apply these settings to a thread, all or none of them.
+    ///
+    /// The settings that will change are read first, then applied in the order of `ConfigStep`.
+    /// If a step fails, the steps already applied are restored in reverse order and
+    /// `Error::ApplyFailed` is returned, telling which step failed and whether restoring
+    /// succeeded. A thread running `Policy::Deadline` can not be restored to it, see
+    /// `PolicyGuard`. Returns an error without changing anything if the priority is invalid for
+    /// the policy or the current settings can not be read.
+    pub fn apply_to<T: Into<Target>>(&self, target: T) -> Result<(), Error> {
+        let target = target.into();
+        if let Some((policy, priority)) = self.policy {
+            validate_priority(policy, priority)?;
+        }
+        let previous = self.snapshot(target)?;
+        self.transaction(&previous, |step, config| config.apply_step(step, target))
+    }
+
+    /// Read the current settings of a thread for the settings that are set in this config.
+    fn snapshot(&self, target: Target) -> Result<SchedulerConfig, Error> {
+        let mut previous = SchedulerConfig::new();
+        if self.affinity.is_some() {
+            previous.affinity = Some(get_affinity_auto(target)?);
+        }
+        if self.policy.is_some() {
+            previous.policy = Some(get_policy_and_priority(target)?);
+        }
+        if self.nice.is_some() {
+            previous.nice = Some(get_priority(Which::Process, target.as_raw())?);
+        }
+        Ok(previous)
+    }
+
+    /// Call `apply` with this config for each step that is set, and with `previous` for the
+    /// steps already applied once one fails.
+    fn transaction<F>(&self, previous: &SchedulerConfig, mut apply: F) -> Result<(), Error>
+        where F: FnMut(ConfigStep, &SchedulerConfig) -> Result<(), Error>
+    {
+        let mut applied = Vec::new();
+        for &step in STEPS.iter().filter(|&&step| self.is_set(step)) {
+            if let Err(error) = apply(step, self) {
+                let mut rollback = None;
+                for &done in applied.iter().rev() {
+                    if let Err(error) = apply(done, previous) {
+                        rollback = rollback.or_else(|| Some(Box::new(error)));
+                    }
+                }
+                return Err(Error::ApplyFailed { step, error: Box::new(error), rollback });
+            }
+            applied.push(step);
+        }
+        Ok(())
+    }
+
+    fn is_set(&self, step: ConfigStep) -> bool {
+        match step {
+            ConfigStep::Affinity => self.affinity.is_some(),
+            ConfigStep::Policy => self.policy.is_some(),
+            ConfigStep::Nice => self.nice.is_some(),
+        }
+    }
+
+    fn apply_step(&self, step: ConfigStep, target: Target) -> Result<(), Error> {
+        match step {
+            ConfigStep::Affinity => match self.affinity {
+                Some(ref cpuset) => set_affinity(target, cpuset),
+                None => Ok(()),
+            },
+            ConfigStep::Policy => match self.policy {
+                Some((policy, priority)) => set_policy_unchecked(target, policy, priority),
+                None => Ok(()),
+            },
+            ConfigStep::Nice => match self.nice {
+                Some(nice) => set_priority(Which::Process, target.as_raw(), nice),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// The serialized form of a `SchedulerConfig`, with the policy and priority apart.
+#[cfg(feature = "serde")]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFields {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    affinity: Option<CpuSet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    policy: Option<Policy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nice: Option<i32>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SchedulerConfig> for ConfigFields {
+    fn from(config: SchedulerConfig) -> ConfigFields {
+        ConfigFields {
+            affinity: config.affinity,
+            policy: config.policy.map(|(policy, _)| policy),
+            priority: config.policy.map(|(_, priority)| priority),
+            nice: config.nice,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ConfigFields> for SchedulerConfig {
+    type Error = String;
+
+    fn try_from(fields: ConfigFields) -> Result<SchedulerConfig, String> {
+        let policy = match (fields.policy, fields.priority) {
+            (Some(policy), priority) => Some((policy, priority.unwrap_or(0))),
+            (None, Some(_)) => return Err("priority given without a policy".to_string()),
+            (None, None) => None,
+        };
+        Ok(SchedulerConfig { affinity: fields.affinity, policy, nice: fields.nice })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::EPERM;
+    use std::thread;
+    use super::{ConfigStep, SchedulerConfig};
+    use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+    use sched::Policy;
+    use target::Target;
+
+    #[test]
+    fn test_apply_to() {
+        let config = SchedulerConfig::new()
+            .affinity(CpuSet::single(0))
+            .policy(Policy::Batch, 0)
+            .nice(5);
+        let current = thread::spawn(move || {
+            config.apply_to(Target::CurrentThread).unwrap();
+            SchedulerConfig::current_for(Target::CurrentThread).unwrap()
+        }).join().unwrap();
+        assert_eq!(Some(&CpuSet::single(0)), current.get_affinity());
+        assert_eq!(Some((Policy::Batch, 0)), current.get_policy());
+        assert_eq!(Some(5), current.get_nice());
+        assert_eq!(SchedulerConfig::new().policy(Policy::Batch, 0).nice(5),
+                   SchedulerConfig::new().nice(5).policy(Policy::Batch, 0));
+    }
+
+    #[test]
+    fn test_apply_to_invalid() {
+        let config = SchedulerConfig::new().nice(5).policy(Policy::Other, 10);
+        let before = thread::spawn(move || {
+            let before = SchedulerConfig::current_for(Target::CurrentThread).unwrap();
+            match config.apply_to(Target::CurrentThread) {
+                Err(Error::InvalidArgument(_)) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+            (before, SchedulerConfig::current_for(Target::CurrentThread).unwrap())
+        }).join().unwrap();
+        assert_eq!(before.0, before.1);
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let config = SchedulerConfig::new()
+            .affinity(CpuSet::single(0))
+            .policy(Policy::Batch, 0)
+            .nice(5);
+        let previous = SchedulerConfig::new()
+            .affinity(CpuSet::all(4))
+            .policy(Policy::Other, 0)
+            .nice(0);
+        let mut calls = Vec::new();
+        let result = config.transaction(&previous, |step, applied| {
+            calls.push((step, applied == &previous));
+            match (step, applied == &previous) {
+                (ConfigStep::Nice, false) | (ConfigStep::Policy, true) => {
+                    Err(Error::from_raw_os_error(EPERM))
+                }
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(vec![(ConfigStep::Affinity, false), (ConfigStep::Policy, false),
+                        (ConfigStep::Nice, false), (ConfigStep::Policy, true),
+                        (ConfigStep::Affinity, true)],
+                   calls);
+        match result {
+            Err(Error::ApplyFailed { step: ConfigStep::Nice, ref error, ref rollback }) => {
+                assert_eq!(ErrorKind::PermissionDenied, error.kind());
+                assert_eq!(Some(EPERM), rollback.as_ref().unwrap().raw_os_error());
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_transaction_skips_unset() {
+        let config = SchedulerConfig::new().nice(5);
+        let mut calls = Vec::new();
+        config.transaction(&SchedulerConfig::new(), |step, _| {
+            calls.push(step);
+            Ok(())
+        }).unwrap();
+        assert_eq!(vec![ConfigStep::Nice], calls);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let config = SchedulerConfig::new()
+            .affinity(CpuSet::from_range(0..4))
+            .policy(Policy::Fifo, 10)
+            .nice(-5);
+        let json = ::serde_json::to_string(&config).unwrap();
+        assert_eq!(r#"{"affinity":"0-3","policy":"fifo","priority":10,"nice":-5}"#, json);
+        assert_eq!(config, ::serde_json::from_str::<SchedulerConfig>(&json).unwrap());
+        assert_eq!("{}", ::serde_json::to_string(&SchedulerConfig::new()).unwrap());
+        let config: SchedulerConfig = ::serde_json::from_str(r#"{"policy":"batch"}"#).unwrap();
+        assert_eq!(Some((Policy::Batch, 0)), config.get_policy());
+        for &invalid in &[r#"{"priority":10}"#, r#"{"cpus":"0"}"#, r#"{"policy":"fifoo"}"#] {
+            assert!(::serde_json::from_str::<SchedulerConfig>(invalid).is_err());
+        }
+    }
+}
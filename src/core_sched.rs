@@ -0,0 +1,141 @@
+//! Core scheduling, letting only threads with the same cookie run on the SMT siblings of a core
+//!
+//! Wraps `prctl(PR_SCHED_CORE, ...)`. Kernels built without `CONFIG_SCHED_CORE`, and systems
+//! without SMT, return errors of kind `ErrorKind::Unsupported`.
+use libc::{c_int, c_ulong, prctl, EINVAL, ENODEV, PR_SCHED_CORE, PR_SCHED_CORE_CREATE,
+           PR_SCHED_CORE_GET, PR_SCHED_CORE_SCOPE_PROCESS_GROUP, PR_SCHED_CORE_SCOPE_THREAD,
+           PR_SCHED_CORE_SCOPE_THREAD_GROUP, PR_SCHED_CORE_SHARE_FROM, PR_SCHED_CORE_SHARE_TO};
+use std::io;
+use error::Error;
+use target::Target;
+
+/// Which threads a core scheduling cookie is applied to, relative to the target thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PidType {
+    /// Only the target thread (`PIDTYPE_PID`)
+    Pid,
+    /// Every thread of the process of the target thread (`PIDTYPE_TGID`)
+    Tgid,
+    /// Every thread of every process in the process group of the target thread (`PIDTYPE_PGID`)
+    Pgid,
+}
+
+impl PidType {
+    fn to_c_int(self) -> c_int {
+        match self {
+            PidType::Pid => PR_SCHED_CORE_SCOPE_THREAD,
+            PidType::Tgid => PR_SCHED_CORE_SCOPE_THREAD_GROUP,
+            PidType::Pgid => PR_SCHED_CORE_SCOPE_PROCESS_GROUP,
+        }
+    }
+}
+
+/// Create a new cookie for the threads selected by `target` and `pid_type`.
+/// Requires `PTRACE_MODE_READ` access to the target.
+pub fn create_cookie<T: Into<Target>>(target: T, pid_type: PidType) -> Result<(), Error> {
+    core_sched(PR_SCHED_CORE_CREATE, target.into(), pid_type, 0)
+}
+
+/// Get the cookie of a thread, where 0 means the thread has no cookie.
+///
+/// The value only tells threads with the same cookie apart, it can not be assigned.
+pub fn get_cookie<T: Into<Target>>(target: T) -> Result<u64, Error> {
+    let mut cookie: u64 = 0;
+    let cookie_ptr = &mut cookie as *mut u64 as c_ulong;
+    core_sched(PR_SCHED_CORE_GET, target.into(), PidType::Pid, cookie_ptr)?;
+    Ok(cookie)
+}
+
+/// Give the cookie of the calling thread to the threads selected by `target` and `pid_type`.
+pub fn share_cookie_to<T: Into<Target>>(target: T, pid_type: PidType) -> Result<(), Error> {
+    core_sched(PR_SCHED_CORE_SHARE_TO, target.into(), pid_type, 0)
+}
+
+/// Take the cookie of the thread `target` for the calling thread.
+pub fn share_cookie_from<T: Into<Target>>(target: T) -> Result<(), Error> {
+    core_sched(PR_SCHED_CORE_SHARE_FROM, target.into(), PidType::Pid, 0)
+}
+
+/// Call `prctl(PR_SCHED_CORE, ...)`, where `EINVAL` means core scheduling is not available as
+/// the other arguments are valid by construction, and `ENODEV` that the CPUs have no SMT.
+fn core_sched(command: c_int, target: Target, pid_type: PidType, arg: c_ulong)
+              -> Result<(), Error> {
+    let pid = target.as_raw() as c_ulong;
+    match unsafe { prctl(PR_SCHED_CORE, command as c_ulong, pid, pid_type.to_c_int() as c_ulong,
+                         arg) } {
+        0 => Ok(()),
+        _ => match Error::last_os_error() {
+            ref error if error.raw_os_error() == Some(EINVAL) => {
+                let message = "core scheduling is not supported by this kernel";
+                Err(Error::Io(io::Error::new(io::ErrorKind::Unsupported, message)))
+            }
+            ref error if error.raw_os_error() == Some(ENODEV) => {
+                let message = "core scheduling is not supported without SMT";
+                Err(Error::Io(io::Error::new(io::ErrorKind::Unsupported, message)))
+            }
+            error => Err(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_cookie, get_cookie, share_cookie_from, share_cookie_to, PidType};
+    use error::ErrorKind;
+    use std::sync::mpsc;
+    use std::thread;
+    use target::{Target, Tid};
+
+    #[test]
+    fn test_create_and_share_cookie() {
+        match get_cookie(Target::CurrentThread) {
+            Err(ref error) if error.kind() == ErrorKind::Unsupported => return,
+            result => assert_eq!(0, result.unwrap()),
+        }
+        create_cookie(Target::CurrentThread, PidType::Pid).unwrap();
+        let cookie = get_cookie(Target::CurrentThread).unwrap();
+        assert_ne!(0, cookie);
+
+        let (sender, receiver) = mpsc::channel();
+        let (done_sender, done_receiver) = mpsc::channel::<()>();
+        let other = thread::spawn(move || {
+            sender.send(Tid::current()).unwrap();
+            done_receiver.recv().unwrap();
+            get_cookie(Target::CurrentThread).unwrap()
+        });
+        let tid = receiver.recv().unwrap();
+        assert_eq!(0, get_cookie(tid).unwrap());
+        share_cookie_to(tid, PidType::Pid).unwrap();
+        done_sender.send(()).unwrap();
+        assert_eq!(cookie, other.join().unwrap());
+    }
+
+    #[test]
+    fn test_share_cookie_from() {
+        let (sender, receiver) = mpsc::channel();
+        let (done_sender, done_receiver) = mpsc::channel::<()>();
+        let owner = thread::spawn(move || {
+            let cookie = create_cookie(Target::CurrentThread, PidType::Pid)
+                .and_then(|()| get_cookie(Target::CurrentThread));
+            sender.send((Tid::current(), cookie)).unwrap();
+            done_receiver.recv().unwrap();
+        });
+        let (tid, cookie) = receiver.recv().unwrap();
+        match cookie {
+            Err(ref error) if error.kind() == ErrorKind::Unsupported => {}
+            cookie => {
+                share_cookie_from(tid).unwrap();
+                assert_eq!(cookie.unwrap(), get_cookie(Target::CurrentThread).unwrap());
+            }
+        }
+        done_sender.send(()).unwrap();
+        owner.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_cookie_missing_thread() {
+        let error = get_cookie(i32::MAX).unwrap_err();
+        let kind = error.kind();
+        assert!(kind == ErrorKind::NoSuchProcess || kind == ErrorKind::Unsupported);
+    }
+}
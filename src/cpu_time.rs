@@ -0,0 +1,75 @@
+//! CPU time consumed by processes and threads, read from their CPU-time clocks
+use libc::{clock_getcpuclockid, clock_gettime, clockid_t, timespec, CLOCK_THREAD_CPUTIME_ID,
+           EINVAL, ESRCH};
+use std::time::Duration;
+use error::Error;
+use target::Pid;
+
+/// Highest process id the kernel can assign, `PID_MAX_LIMIT` on 64-bit systems.
+const PID_MAX_LIMIT: i32 = 1 << 22;
+
+/// Get the CPU time consumed by all threads of a process, including exited ones.
+///
+/// Fails with `ErrorKind::NoSuchProcess` if the process does not exist or exits meanwhile, and
+/// with `ErrorKind::PermissionDenied` if the system does not allow reading its clock.
+pub fn process_cpu_time(pid: Pid) -> Result<Duration, Error> {
+    // Larger ids can not exist, but would be encoded as the clock of another process
+    if pid.as_raw() < 0 || pid.as_raw() > PID_MAX_LIMIT {
+        return Err(Error::from_raw_os_error(ESRCH));
+    }
+    let mut clock: clockid_t = 0;
+    match unsafe { clock_getcpuclockid(pid.as_raw(), &mut clock) } {
+        0 => {}
+        code => return Err(Error::from_raw_os_error(code)),
+    }
+    read_clock(clock).map_err(|error| match error.raw_os_error() {
+        // The clock of a process that exited is no longer valid
+        Some(EINVAL) => Error::from_raw_os_error(ESRCH),
+        _ => error,
+    })
+}
+
+/// Get the CPU time consumed by the calling thread.
+pub fn thread_cpu_time() -> Result<Duration, Error> {
+    read_clock(CLOCK_THREAD_CPUTIME_ID)
+}
+
+fn read_clock(clock: clockid_t) -> Result<Duration, Error> {
+    let mut time = timespec { tv_sec: 0, tv_nsec: 0 };
+    match unsafe { clock_gettime(clock, &mut time) } {
+        0 => Ok(Duration::new(time.tv_sec as u64, time.tv_nsec as u32)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use super::{process_cpu_time, thread_cpu_time};
+    use error::ErrorKind;
+    use target::Pid;
+
+    fn spin() -> u64 {
+        (0..1_000_000u64).fold(0, |sum, i| sum.wrapping_add(i * i))
+    }
+
+    #[test]
+    fn test_thread_cpu_time() {
+        let before = thread_cpu_time().unwrap();
+        assert!(spin() > 0);
+        let after = thread_cpu_time().unwrap();
+        assert!(after > before);
+        assert!(process_cpu_time(Pid::this()).unwrap() >= after - before);
+    }
+
+    #[test]
+    fn test_process_cpu_time_missing() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = Pid::from(&child);
+        child.wait().unwrap();
+        let error = process_cpu_time(pid).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+        let error = process_cpu_time(Pid::from_raw(i32::MAX)).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+}
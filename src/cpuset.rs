@@ -1,33 +1,215 @@
 //! A CPU bitmask implementation to be used with the sched_[gs]etaffinity functions.
 
 use libc::{c_void, cpu_set_t, sched_getaffinity, sched_setaffinity};
+use std::borrow::Cow;
+use std::fmt;
+use std::iter::{Enumerate, FromIterator, Peekable};
 use std::mem;
-use std::ptr;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
+use std::ops::{Bound, Deref, DerefMut, RangeBounds, RangeInclusive};
+use std::str::FromStr;
+use error::Error;
+use target::Target;
 use std::cmp;
+use std::convert::TryFrom;
+use std::slice;
 
 type Mask = u64;
 const MASK_BITS: usize = 64;
+/// Number of words stored without a heap allocation.
+const INLINE_WORDS: usize = 2;
+
+/// Unsigned integer types that can be used as a mask in `CpuSet::from_mask`.
+///
+/// This trait is sealed and can not be implemented outside of this crate.
+pub trait CpuMask: private::Sealed + Copy {
+    #[doc(hidden)]
+    fn to_u128(self) -> u128;
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_cpu_mask {
+    ($($int:ty),*) => {
+        $(
+            impl private::Sealed for $int {}
+
+            impl CpuMask for $int {
+                fn to_u128(self) -> u128 {
+                    self as u128
+                }
+            }
+        )*
+    }
+}
+
+impl_cpu_mask!(u8, u16, u32, u64, u128, usize);
+
+/// CPUs accepted by `set_affinity`: a `CpuSet`, by value or borrowed, or anything `CpuSet`
+/// converts from, such as a plain `u64` mask.
+///
+/// This trait is sealed and can not be implemented outside of this crate.
+pub trait ToCpuSet<'a>: private::Sealed {
+    #[doc(hidden)]
+    fn to_cpuset(self) -> Cow<'a, CpuSet>;
+}
+
+impl private::Sealed for CpuSet {}
+
+impl<'a> ToCpuSet<'a> for CpuSet {
+    fn to_cpuset(self) -> Cow<'a, CpuSet> {
+        Cow::Owned(self)
+    }
+}
+
+impl private::Sealed for &CpuSet {}
+
+impl<'a> ToCpuSet<'a> for &'a CpuSet {
+    fn to_cpuset(self) -> Cow<'a, CpuSet> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl private::Sealed for &[u64] {}
+
+macro_rules! impl_to_cpuset_from {
+    ($($from:ty),*) => {
+        $(
+            impl<'a> ToCpuSet<'a> for $from {
+                fn to_cpuset(self) -> Cow<'a, CpuSet> {
+                    Cow::Owned(CpuSet::from(self))
+                }
+            }
+        )*
+    }
+}
+
+impl_to_cpuset_from!(u32, u64, &'a [u64]);
 
 /// Struct representing a bitmask to be used with the affinity functions.
 /// Meant to represent the `CPU_*` macros from `sched.h`
+#[derive(Clone)]
 pub struct CpuSet {
-    mask: Vec<Mask>,
+    mask: Words,
+}
+
+/// The words of a mask. Masks of up to `INLINE_WORDS` words are stored inline, larger masks
+/// spill to the heap.
+#[derive(Clone)]
+enum Words {
+    Inline { words: [Mask; INLINE_WORDS], len: usize },
+    Heap(Vec<Mask>),
+}
+
+impl Words {
+    /// Create `len` words with all bits cleared.
+    fn zeroed(len: usize) -> Words {
+        if len <= INLINE_WORDS {
+            Words::Inline { words: [0; INLINE_WORDS], len }
+        } else {
+            Words::Heap(vec![0; len])
+        }
+    }
+
+    /// Change the number of words to `new_len`, new words have all bits cleared.
+    fn resize(&mut self, new_len: usize) {
+        match *self {
+            Words::Inline { ref mut words, ref mut len } if new_len <= INLINE_WORDS => {
+                for word in &mut words[new_len..] {
+                    *word = 0;
+                }
+                *len = new_len;
+            }
+            Words::Inline { .. } => {
+                let mut words = self.to_vec();
+                words.resize(new_len, 0);
+                *self = Words::Heap(words);
+            }
+            Words::Heap(ref mut words) => words.resize(new_len, 0),
+        }
+    }
+
+    /// Release unused heap memory, moving the words inline if they fit.
+    fn shrink_to_fit(&mut self) {
+        if self.len() <= INLINE_WORDS {
+            let mut words = [0; INLINE_WORDS];
+            words[..self.len()].copy_from_slice(self);
+            *self = Words::Inline { words, len: self.len() };
+        } else if let Words::Heap(ref mut words) = *self {
+            words.shrink_to_fit();
+        }
+    }
+}
+
+impl Deref for Words {
+    type Target = [Mask];
+
+    fn deref(&self) -> &[Mask] {
+        match *self {
+            Words::Inline { ref words, len } => &words[..len],
+            Words::Heap(ref words) => words,
+        }
+    }
+}
+
+impl DerefMut for Words {
+    fn deref_mut(&mut self) -> &mut [Mask] {
+        match *self {
+            Words::Inline { ref mut words, len } => &mut words[..len],
+            Words::Heap(ref mut words) => words,
+        }
+    }
 }
 
 impl CpuSet {
     /// Create a new `CpuSet` with room for `num_cpus` CPUs, no cpu will be active.
     /// Equivalent of `CPU_ALLOC`
     pub fn new(num_cpus: usize) -> CpuSet {
-        let elements = (num_cpus + MASK_BITS - 1) / MASK_BITS;
-        let mask = vec![0; cmp::max(elements, 1)];
-        CpuSet { mask: mask }
+        let elements = num_cpus.div_ceil(MASK_BITS);
+        CpuSet { mask: Words::zeroed(cmp::max(elements, 1)) }
+    }
+
+    /// Create a new `CpuSet` with room for `num_cpus` CPUs, all of them active.
+    pub fn all(num_cpus: usize) -> CpuSet {
+        let mut cpuset = Self::new(num_cpus);
+        cpuset.set_range(..num_cpus);
+        cpuset
+    }
+
+    /// Create a new `CpuSet` with the CPUs in `range` active.
+    /// Shorthand for using `new` and `set_range`
+    pub fn from_range<R: RangeBounds<usize>>(range: R) -> CpuSet {
+        let mut cpuset = Self::new(0);
+        cpuset.set_range(range);
+        cpuset
+    }
+
+    /// Create a new `CpuSet` with the CPUs listed in `cpus` active.
+    pub fn from_slice(cpus: &[usize]) -> CpuSet {
+        cpus.iter().cloned().collect()
+    }
+
+    /// Create a new `CpuSet` with room for `cpus.len()` CPUs, where CPU `i` is active if
+    /// `cpus[i]` is true.
+    pub fn from_bools(cpus: &[bool]) -> CpuSet {
+        let mut cpuset = Self::new(cpus.len());
+        for (cpu, &active) in cpus.iter().enumerate() {
+            if active {
+                cpuset.set(cpu);
+            }
+        }
+        cpuset
     }
 
     /// Create a new `CpuSet` from a given mask. For example a u64 or a u8.
-    pub fn from_mask<T>(mask: T) -> CpuSet {
+    /// Bit `n` of the mask activates CPU `n`.
+    pub fn from_mask<T: CpuMask>(mask: T) -> CpuSet {
+        let bits = mask.to_u128();
         let mut cpuset = Self::new(8 * mem::size_of::<T>());
-        unsafe {
-            ptr::write(cpuset.mut_mask_ptr() as *mut T, mask);
+        for (elem, word) in cpuset.mask.iter_mut().enumerate() {
+            *word = (bits >> (elem * MASK_BITS)) as Mask;
         }
         cpuset
     }
@@ -46,12 +228,25 @@ impl CpuSet {
     pub fn set(&mut self, cpu: usize) {
         let elem = cpu / MASK_BITS;
         let bit = cpu % MASK_BITS;
-        while elem > self.mask.len() {
-            self.mask.push(0);
-        }
+        self.grow(elem + 1);
         self.mask[elem] |= 1 << bit;
     }
 
+    /// Expand the mask with inactive CPUs until it holds at least `elements` words.
+    fn grow(&mut self, elements: usize) {
+        if elements > self.mask.len() {
+            self.mask.resize(elements);
+        }
+    }
+
+    /// Shrink the mask to the smallest size holding all active CPUs, keeping room for at least
+    /// one word. This reduces `byte_size`, and thereby the size passed to the affinity calls.
+    pub fn shrink_to_fit(&mut self) {
+        let elements = self.mask.iter().rposition(|&word| word != 0).map_or(1, |elem| elem + 1);
+        self.mask.resize(elements);
+        self.mask.shrink_to_fit();
+    }
+
     /// Clear a given `cpu` on this `CpuSet`.
     /// If the given `cpu` does not fit within the current `CpuSet` nothing will happen.
     /// Equivalent of `CPU_CLR`.
@@ -59,7 +254,52 @@ impl CpuSet {
         let elem = cpu / MASK_BITS;
         let bit = cpu % MASK_BITS;
         if elem < self.mask.len() {
-            self.mask[elem] ^= 1 << bit;
+            self.mask[elem] &= !(1 << bit);
+        }
+    }
+
+    /// Activate all CPUs in `range` on this `CpuSet`.
+    /// If the range does not fit in the current `CpuSet`, it will be expanded to fit.
+    /// A range without an end activates all CPUs up to the current capacity.
+    pub fn set_range<R: RangeBounds<usize>>(&mut self, range: R) {
+        let (start, end) = self.bounds(range);
+        self.grow(end.div_ceil(MASK_BITS));
+        self.apply_range(start, end, |word, bits| word | bits);
+    }
+
+    /// Clear all CPUs in `range` on this `CpuSet`.
+    /// CPUs in the range that do not fit within the current `CpuSet` are ignored.
+    pub fn clear_range<R: RangeBounds<usize>>(&mut self, range: R) {
+        let (start, end) = self.bounds(range);
+        let end = cmp::min(end, self.capacity());
+        self.apply_range(start, end, |word, bits| word & !bits);
+    }
+
+    /// Convert `range` to a half open `(start, end)` pair, unbounded ends stop at the capacity.
+    fn bounds<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.capacity(),
+        };
+        (start, end)
+    }
+
+    /// Combine every word overlapping the CPUs `start..end` with the bits of that overlap.
+    fn apply_range<F: Fn(Mask, Mask) -> Mask>(&mut self, start: usize, end: usize, op: F) {
+        let mut cpu = start;
+        while cpu < end {
+            let elem = cpu / MASK_BITS;
+            let bit = cpu % MASK_BITS;
+            let count = cmp::min(MASK_BITS - bit, end - cpu);
+            let bits = if count == MASK_BITS { !0 } else { ((1 << count) - 1) << bit };
+            self.mask[elem] = op(self.mask[elem], bits);
+            cpu += count;
         }
     }
 
@@ -69,96 +309,683 @@ impl CpuSet {
     pub fn is_set(&self, cpu: usize) -> bool {
         let elem = cpu / MASK_BITS;
         let bit = cpu % MASK_BITS;
-        if elem > self.len() {
+        if elem >= self.mask.len() {
             false
         } else {
             self.mask[elem] & (1 << bit) != 0
         }
     }
 
+    /// Combine every word of this mask with the corresponding word of `other`.
+    /// The mask is first expanded to the size of `other`, missing words count as zero.
+    fn combine<F: Fn(Mask, Mask) -> Mask>(&mut self, other: &CpuSet, op: F) {
+        self.grow(other.mask.len());
+        for (elem, word) in self.mask.iter_mut().enumerate() {
+            *word = op(*word, other.mask.get(elem).cloned().unwrap_or(0));
+        }
+    }
+
+    /// Create a new `CpuSet` with the CPUs active in this set but not in `other`.
+    pub fn difference(&self, other: &CpuSet) -> CpuSet {
+        let mut cpuset = self.clone();
+        cpuset.remove_all(other);
+        cpuset
+    }
+
+    /// Clear every CPU active in `other` from this `CpuSet`.
+    pub fn remove_all(&mut self, other: &CpuSet) {
+        for (word, other_word) in self.mask.iter_mut().zip(other.mask.iter()) {
+            *word &= !other_word;
+        }
+    }
+
+    /// Check if every CPU active in this `CpuSet` is also active in `other`.
+    pub fn is_subset(&self, other: &CpuSet) -> bool {
+        self.mask.iter().enumerate().all(|(elem, &word)| {
+            word & !other.mask.get(elem).cloned().unwrap_or(0) == 0
+        })
+    }
+
+    /// Check if every CPU active in `other` is also active in this `CpuSet`.
+    pub fn is_superset(&self, other: &CpuSet) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Get the lowest CPU active in this `CpuSet`, or `None` if it is empty.
+    pub fn first_set(&self) -> Option<usize> {
+        self.mask
+            .iter()
+            .enumerate()
+            .find(|&(_, &word)| word != 0)
+            .map(|(elem, word)| elem * MASK_BITS + word.trailing_zeros() as usize)
+    }
+
+    /// Get the highest CPU active in this `CpuSet`, or `None` if it is empty.
+    pub fn last_set(&self) -> Option<usize> {
+        self.mask
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|&(_, &word)| word != 0)
+            .map(|(elem, word)| elem * MASK_BITS + MASK_BITS - 1 - word.leading_zeros() as usize)
+    }
+
+    /// Split the active CPUs into `n` disjoint `CpuSet`s of contiguous CPUs, as evenly as possible.
+    ///
+    /// The lowest CPUs go to the first set. When the CPUs do not divide evenly the first sets get
+    /// one CPU more than the last. When `n` is larger than the number of active CPUs the last
+    /// sets are empty. Returns an error if `n` is 0.
+    pub fn distribute(&self, n: usize) -> Result<Vec<CpuSet>, Error> {
+        if n == 0 {
+            return Err(Error::InvalidArgument("can not distribute CPUs over 0 sets".into()));
+        }
+        let count = self.iter().count();
+        let mut cpus = self.iter();
+        Ok((0..n)
+            .map(|i| {
+                let size = count / n + if i < count % n { 1 } else { 0 };
+                cpus.by_ref().take(size).collect()
+            })
+            .collect())
+    }
+
+    /// Get a `CpuSet` with the `n` lowest CPUs active in this `CpuSet`.
+    /// All active CPUs are returned when fewer than `n` are active, and none when `n` is 0.
+    pub fn take_first(&self, n: usize) -> CpuSet {
+        self.iter().take(n).collect()
+    }
+
+    /// Get a `CpuSet` with the `n` highest CPUs active in this `CpuSet`.
+    /// All active CPUs are returned when fewer than `n` are active, and none when `n` is 0.
+    pub fn take_last(&self, n: usize) -> CpuSet {
+        let cpus = self.to_vec();
+        CpuSet::from_slice(&cpus[cpus.len().saturating_sub(n)..])
+    }
+
+    /// Iterate over the CPUs active in this `CpuSet`, in ascending order.
+    pub fn iter(&self) -> Cpus<'_> {
+        Cpus {
+            words: self.mask.iter().enumerate(),
+            base: 0,
+            word: 0,
+        }
+    }
+
+    /// Iterate over the maximal runs of consecutive active CPUs, in ascending order.
+    pub fn ranges(&self) -> Ranges<'_> {
+        Ranges { cpus: self.iter().peekable() }
+    }
+
+    /// Get the CPUs active in this `CpuSet`, in ascending order.
+    pub fn to_vec(&self) -> Vec<usize> {
+        self.iter().collect()
+    }
+
+    /// Get if each of the CPUs `0..num_cpus` is active in this `CpuSet`.
+    pub fn to_bools(&self, num_cpus: usize) -> Vec<bool> {
+        (0..num_cpus).map(|cpu| self.is_set(cpu)).collect()
+    }
+
+    /// Write the active CPUs as ranges separated by `separator`.
+    fn write_list(&self, f: &mut fmt::Formatter, separator: &str) -> fmt::Result {
+        for (i, range) in self.ranges().enumerate() {
+            if i > 0 {
+                f.write_str(separator)?;
+            }
+            if range.start() == range.end() {
+                write!(f, "{}", range.start())?;
+            } else {
+                write!(f, "{}-{}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+
     /// Get the number of bytes in the mask.
     /// Produces the same results as `CPU_ALLOC_SIZE`.
-    pub fn len(&self) -> usize {
+    pub fn byte_size(&self) -> usize {
         (MASK_BITS / 8) * self.mask.len()
     }
 
+    /// Get the number of bytes in the mask. Deprecated alias of `byte_size`.
+    #[deprecated(note = "does not count active CPUs, use `byte_size` instead")]
+    pub fn len(&self) -> usize {
+        self.byte_size()
+    }
+
+    /// Get the number of CPUs this `CpuSet` has room for without expanding.
+    pub fn capacity(&self) -> usize {
+        MASK_BITS * self.mask.len()
+    }
+
+    /// Check if no CPU is active in this `CpuSet`.
+    /// The kernel refuses to set an empty affinity mask.
+    pub fn is_empty(&self) -> bool {
+        self.mask.iter().all(|&word| word == 0)
+    }
+
+    /// Get the words of the bitmask.
+    /// CPU `n` is bit `n % 64` of word `n / 64`, so CPU 0 is the least significant bit of the
+    /// first word.
+    pub fn as_slice(&self) -> &[u64] {
+        &self.mask
+    }
+
+    /// Get the words of the bitmask for modification. See `as_slice` for the layout.
+    pub fn as_mut_slice(&mut self) -> &mut [u64] {
+        &mut self.mask
+    }
+
     /// Get the raw pointer to the bitmask
     /// Any modification of the `CpuSet` after this call might invalidate the pointer.
     pub fn mask_ptr(&self) -> *const c_void {
-        self.mask.as_ptr() as *const c_void
+        self.as_slice().as_ptr() as *const c_void
     }
 
     /// Get a mutable raw pointer to the bitmask.
     /// Any modification of the `CpuSet` after this call might invalidate the pointer.
     pub fn mut_mask_ptr(&mut self) -> *mut c_void {
-        self.mask.as_mut_ptr() as *mut c_void
+        self.as_mut_slice().as_mut_ptr() as *mut c_void
+    }
+
+    /// Parse a `CpuSet` from the comma separated hexadecimal format used by the kernel in e.g.
+    /// `/proc/irq/*/smp_affinity` and `/sys/devices/system/cpu/*/topology/thread_siblings`.
+    ///
+    /// Every comma separated chunk holds 32 CPUs, with the highest CPUs in the first chunk.
+    pub fn from_hex_mask(s: &str) -> Result<CpuSet, ParseCpuSetError> {
+        let chunks: Vec<&str> = s.trim().split(',').collect();
+        let mut cpuset = CpuSet::new(32 * chunks.len());
+        for (i, chunk) in chunks.iter().rev().enumerate() {
+            let valid = !chunk.is_empty() && chunk.len() <= 8 &&
+                        chunk.chars().all(|c| c.is_ascii_hexdigit());
+            let bits = match u32::from_str_radix(chunk, 16) {
+                Ok(bits) if valid => bits as Mask,
+                _ => return Err(ParseCpuSetError::new(chunk, "not a 32 bit hexadecimal chunk")),
+            };
+            cpuset.mask[i / 2] |= bits << (32 * (i % 2));
+        }
+        Ok(cpuset)
+    }
+
+    /// Format this `CpuSet` in the comma separated hexadecimal format parsed by `from_hex_mask`.
+    /// The output covers every CPU this `CpuSet` has room for.
+    pub fn to_hex_mask_string(&self) -> String {
+        let chunks: Vec<String> = self.mask
+            .iter()
+            .rev()
+            .flat_map(|&word| vec![word >> 32, word & 0xffff_ffff])
+            .map(|chunk| format!("{:08x}", chunk))
+            .collect();
+        chunks.join(",")
+    }
+
+    /// Encode this `CpuSet` as bytes, e.g. to send it to another process.
+    ///
+    /// The layout does not depend on the host: CPU `n` is bit `n % 8` of byte `n / 8`, so CPU 0
+    /// is the least significant bit of the first byte. The output covers every CPU this `CpuSet`
+    /// has room for and is decoded by `from_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.mask.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+    }
+
+    /// Decode a `CpuSet` from bytes in the layout produced by `to_bytes`.
+    /// The result has room for at least `8 * bytes.len()` CPUs.
+    pub fn from_bytes(bytes: &[u8]) -> CpuSet {
+        let mut cpuset = CpuSet::new(8 * bytes.len());
+        for (elem, chunk) in bytes.chunks(MASK_BITS / 8).enumerate() {
+            let mut word = [0; MASK_BITS / 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            cpuset.mask[elem] = Mask::from_le_bytes(word);
+        }
+        cpuset
     }
 
     /// Represent this `CpuSet` as a `u64`.
-    /// Will return an `Err` if the `CpuSet` is too large to be written to a `u64`
-    pub fn as_u64(&self) -> Result<u64, ()> {
-        let src_size = self.len();
-        let out_size = mem::size_of::<u64>();
-        if src_size > out_size {
-            Err(())
-        } else {
-            let mut mask: u64 = 0;
-            unsafe {
-                ptr::copy(self.mask_ptr(),
-                          (&mut mask) as *mut _ as *mut c_void,
-                          src_size)
+    /// Will return an `Err` if a CPU above 63 is active.
+    pub fn as_u64(&self) -> Result<u64, TryFromCpuSetError> {
+        u64::try_from(self)
+    }
+
+    /// Represent this `CpuSet` as a `u128`.
+    /// Will return an `Err` if a CPU above 127 is active.
+    pub fn as_u128(&self) -> Result<u128, TryFromCpuSetError> {
+        self.to_bits(128)
+    }
+
+    /// Represent the `CpuSet` as an integer of `width` bits, where all active CPUs must fit.
+    fn to_bits(&self, width: usize) -> Result<u128, TryFromCpuSetError> {
+        match self.last_set() {
+            Some(cpu) if cpu >= width => Err(TryFromCpuSetError { width, cpu }),
+            _ => {
+                let words = self.mask.iter().take(128 / MASK_BITS).enumerate();
+                Ok(words.fold(0, |bits, (elem, &word)| bits | (word as u128) << (elem * MASK_BITS)))
             }
-            Ok(mask)
         }
     }
 
-    /// Sets the affinity described by this `CpuSet` to a given `pid`.
-    pub fn set_affinity(&self, pid: i32) -> Result<(), ()> {
-        match unsafe { sched_setaffinity(pid, self.len(), self.mask_ptr() as *const cpu_set_t) } {
+    /// Sets the affinity described by this `CpuSet` to a given thread.
+    pub fn set_affinity<T: Into<Target>>(&self, target: T) -> Result<(), Error> {
+        let pid = target.into().as_raw();
+        match unsafe { sched_setaffinity(pid, self.byte_size(), self.mask_ptr() as *const cpu_set_t) } {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(Error::last_os_error()),
         }
     }
 
-    /// Fetch the affinity for a given `pid` as a `CpuSet`.
-    pub fn get_affinity(pid: i32, num_cpus: usize) -> Result<CpuSet, ()> {
+    /// Fetch the affinity for a given thread as a `CpuSet`.
+    pub fn get_affinity<T: Into<Target>>(target: T, num_cpus: usize) -> Result<CpuSet, Error> {
+        let pid = target.into().as_raw();
         let mut cpuset = CpuSet::new(num_cpus);
         match unsafe {
-            sched_getaffinity(pid, cpuset.len(), cpuset.mut_mask_ptr() as *mut cpu_set_t)
+            sched_getaffinity(pid, cpuset.byte_size(), cpuset.mut_mask_ptr() as *mut cpu_set_t)
         } {
             0 => Ok(cpuset),
-            _ => Err(()),
+            _ => Err(Error::last_os_error()),
+        }
+    }
+}
+
+/// Lists the active CPUs, with consecutive CPUs collapsed into ranges. E.g. `CpuSet {0, 2, 5-8}`
+impl fmt::Debug for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CpuSet {")?;
+        self.write_list(f, ", ")?;
+        f.write_str("}")
+    }
+}
+
+/// Formats the active CPUs in the list format used by `taskset` and sysfs, e.g. `0-3,8,10-11`.
+/// An empty `CpuSet` formats as an empty string.
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_list(f, ",")
+    }
+}
+
+/// Parses the list format used by `taskset` and sysfs, e.g. `0-3,7,16-23`.
+///
+/// Whitespace around components is ignored and ranges are inclusive. A range can be followed by
+/// a stride, `0-10:2` selects every other CPU from 0 to 10. An empty string gives an empty set.
+impl FromStr for CpuSet {
+    type Err = ParseCpuSetError;
+
+    fn from_str(s: &str) -> Result<CpuSet, ParseCpuSetError> {
+        let mut cpuset = CpuSet::new(0);
+        if s.trim().is_empty() {
+            return Ok(cpuset);
+        }
+        for component in s.split(',') {
+            let (start, end, stride) = parse_list_component(component.trim())?;
+            if stride == 1 {
+                cpuset.set_range(start..=end);
+            } else {
+                cpuset.extend((start..=end).step_by(stride));
+            }
+        }
+        Ok(cpuset)
+    }
+}
+
+/// Parse a single `N`, `N-M` or `N-M:S` component of a CPU list into `(start, end, stride)`.
+fn parse_list_component(component: &str) -> Result<(usize, usize, usize), ParseCpuSetError> {
+    let error = |reason| Err(ParseCpuSetError::new(component, reason));
+    if component.is_empty() {
+        return error("empty component");
+    }
+    let (range, stride) = match component.find(':') {
+        Some(i) => (&component[..i], Some(&component[i + 1..])),
+        None => (component, None),
+    };
+    let (start, end) = match range.find('-') {
+        Some(i) => (&range[..i], &range[i + 1..]),
+        None if stride.is_some() => return error("stride given without a range"),
+        None => (range, range),
+    };
+    let parse = |number: &str| number.trim().parse::<usize>();
+    let (start, end) = match (parse(start), parse(end)) {
+        (Ok(start), Ok(end)) => (start, end),
+        _ => return error("not a CPU number or range"),
+    };
+    if start > end {
+        return error("range start is greater than range end");
+    }
+    let stride = match stride.map(parse) {
+        None => 1,
+        Some(Ok(stride)) if stride > 0 => stride,
+        Some(_) => return error("stride must be a positive number"),
+    };
+    Ok((start, end, stride))
+}
+
+/// Error returned when parsing a `CpuSet` from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCpuSetError {
+    component: String,
+    reason: &'static str,
+}
+
+impl ParseCpuSetError {
+    fn new(component: &str, reason: &'static str) -> ParseCpuSetError {
+        ParseCpuSetError {
+            component: component.to_owned(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for ParseCpuSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid CPU set component `{}`: {}", self.component, self.reason)
+    }
+}
+
+impl ::std::error::Error for ParseCpuSetError {}
+
+/// Serializes as the CPU list string, e.g. `"0-3,8"`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for CpuSet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserializes from either the CPU list string or a sequence of CPU indices.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CpuSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<CpuSet, D::Error> {
+        struct CpuSetVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for CpuSetVisitor {
+            type Value = CpuSet;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a CPU list string or a sequence of CPU indices")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<CpuSet, E> {
+                s.parse().map_err(E::custom)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<CpuSet, A::Error> {
+                let mut cpuset = CpuSet::new(0);
+                while let Some(cpu) = seq.next_element()? {
+                    cpuset.set(cpu);
+                }
+                Ok(cpuset)
+            }
+        }
+
+        deserializer.deserialize_any(CpuSetVisitor)
+    }
+}
+
+/// Two `CpuSet`s are equal when the same CPUs are active, regardless of their sizes.
+/// Equivalent of `CPU_EQUAL`.
+impl PartialEq for CpuSet {
+    fn eq(&self, other: &CpuSet) -> bool {
+        let (short, long) = if self.mask.len() <= other.mask.len() {
+            (&self.mask, &other.mask)
+        } else {
+            (&other.mask, &self.mask)
+        };
+        short[..] == long[..short.len()] && long[short.len()..].iter().all(|&word| word == 0)
+    }
+}
+
+impl Eq for CpuSet {}
+
+macro_rules! impl_bit_op {
+    ($op:ident, $method:ident, $op_assign:ident, $method_assign:ident, $f:expr, $doc:expr) => {
+        #[doc = $doc]
+        impl<'a, 'b> $op<&'b CpuSet> for &'a CpuSet {
+            type Output = CpuSet;
+
+            fn $method(self, rhs: &'b CpuSet) -> CpuSet {
+                let mut cpuset = self.clone();
+                cpuset.combine(rhs, $f);
+                cpuset
+            }
+        }
+
+        #[doc = $doc]
+        impl<'a> $op_assign<&'a CpuSet> for CpuSet {
+            fn $method_assign(&mut self, rhs: &'a CpuSet) {
+                self.combine(rhs, $f);
+            }
+        }
+    }
+}
+
+impl_bit_op!(BitAnd, bitand, BitAndAssign, bitand_assign, |a, b| a & b,
+             "Intersection of two `CpuSet`s. Equivalent of `CPU_AND`.");
+impl_bit_op!(BitOr, bitor, BitOrAssign, bitor_assign, |a, b| a | b,
+             "Union of two `CpuSet`s. Equivalent of `CPU_OR`.");
+impl_bit_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, |a, b| a ^ b,
+             "CPUs active in exactly one of two `CpuSet`s. Equivalent of `CPU_XOR`.");
+
+macro_rules! impl_try_from_cpuset {
+    ($int:ty) => {
+        /// Succeeds if every active CPU fits in the integer, regardless of the `CpuSet` size.
+        impl<'a> TryFrom<&'a CpuSet> for $int {
+            type Error = TryFromCpuSetError;
+
+            fn try_from(cpuset: &'a CpuSet) -> Result<$int, TryFromCpuSetError> {
+                cpuset.to_bits(8 * mem::size_of::<$int>()).map(|bits| bits as $int)
+            }
+        }
+    }
+}
+
+impl_try_from_cpuset!(u64);
+impl_try_from_cpuset!(u128);
+impl_try_from_cpuset!(usize);
+
+/// Error returned when a `CpuSet` does not fit in an integer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TryFromCpuSetError {
+    width: usize,
+    cpu: usize,
+}
+
+impl TryFromCpuSetError {
+    /// The number of bits in the integer converted to.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The highest CPU active in the `CpuSet`, that did not fit in the integer.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+}
+
+impl fmt::Display for TryFromCpuSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CPU {} does not fit in a {} bit integer", self.cpu, self.width)
+    }
+}
+
+impl ::std::error::Error for TryFromCpuSetError {}
+
+impl AsRef<CpuSet> for CpuSet {
+    fn as_ref(&self) -> &CpuSet {
+        self
+    }
+}
+
+impl From<u32> for CpuSet {
+    fn from(mask: u32) -> CpuSet {
+        CpuSet::from_mask(mask)
+    }
+}
+
+impl From<u64> for CpuSet {
+    fn from(mask: u64) -> CpuSet {
+        CpuSet::from_mask(mask)
+    }
+}
+
+/// Every `u64` becomes one word of the mask, see `CpuSet::as_slice` for the layout.
+impl<'a> From<&'a [u64]> for CpuSet {
+    fn from(words: &'a [u64]) -> CpuSet {
+        let mut cpuset = CpuSet::new(0);
+        cpuset.grow(words.len());
+        cpuset.mask[..words.len()].copy_from_slice(words);
+        cpuset
+    }
+}
+
+impl<'a> From<&'a CpuSet> for Vec<usize> {
+    fn from(cpuset: &'a CpuSet) -> Vec<usize> {
+        cpuset.to_vec()
+    }
+}
+
+impl FromIterator<usize> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> CpuSet {
+        let mut cpuset = CpuSet::new(0);
+        cpuset.extend(iter);
+        cpuset
+    }
+}
+
+impl Extend<usize> for CpuSet {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for cpu in iter {
+            self.set(cpu);
         }
     }
 }
 
+impl<'a> IntoIterator for &'a CpuSet {
+    type Item = usize;
+    type IntoIter = Cpus<'a>;
+
+    fn into_iter(self) -> Cpus<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the active CPUs of a `CpuSet`, created by `CpuSet::iter`.
+pub struct Cpus<'a> {
+    words: Enumerate<slice::Iter<'a, Mask>>,
+    base: usize,
+    word: Mask,
+}
+
+impl<'a> Iterator for Cpus<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            let (elem, &word) = self.words.next()?;
+            self.base = elem * MASK_BITS;
+            self.word = word;
+        }
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.base + bit)
+    }
+}
+
+/// Iterator over runs of consecutive active CPUs, created by `CpuSet::ranges`.
+pub struct Ranges<'a> {
+    cpus: Peekable<Cpus<'a>>,
+}
+
+impl<'a> Iterator for Ranges<'a> {
+    type Item = RangeInclusive<usize>;
+
+    fn next(&mut self) -> Option<RangeInclusive<usize>> {
+        let start = self.cpus.next()?;
+        let mut end = start;
+        while self.cpus.peek() == Some(&(end + 1)) {
+            end += 1;
+            self.cpus.next();
+        }
+        Some(start..=end)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::convert::TryFrom;
     use std::ops::BitXor;
-    use super::{CpuSet, MASK_BITS};
+    use super::{CpuSet, Words, MASK_BITS};
 
     #[test]
     fn test_new_one_byte() {
         let mut cpuset = CpuSet::new(7);
-        assert_eq!(MASK_BITS / 8, cpuset.len());
+        assert_eq!(MASK_BITS / 8, cpuset.byte_size());
         assert_eq!(0, cpuset.as_u64().unwrap());
         cpuset = CpuSet::new(1);
-        assert_eq!(MASK_BITS / 8, cpuset.len());
+        assert_eq!(MASK_BITS / 8, cpuset.byte_size());
         assert_eq!(0, cpuset.as_u64().unwrap());
         cpuset = CpuSet::new(0);
-        assert_eq!(MASK_BITS / 8, cpuset.len());
+        assert_eq!(MASK_BITS / 8, cpuset.byte_size());
         assert_eq!(0, cpuset.as_u64().unwrap());
     }
 
     #[test]
     fn test_new_many_bytes() {
         let cpuset = CpuSet::new(125);
-        assert_eq!(16, cpuset.len());
+        assert_eq!(16, cpuset.byte_size());
+    }
+
+    #[test]
+    fn test_all() {
+        assert!(CpuSet::all(0).is_empty());
+        assert_eq!(1, CpuSet::all(1).as_u64().unwrap());
+        assert_eq!(u64::MAX, CpuSet::all(64).as_u64().unwrap());
+        for &num_cpus in &[65, 96, 128] {
+            let cpuset = CpuSet::all(num_cpus);
+            assert_eq!(16, cpuset.byte_size());
+            assert_eq!((0..num_cpus).collect::<Vec<_>>(), cpuset.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_from_range() {
+        assert_eq!(0xffff_0000, CpuSet::from_range(16..32).as_u64().unwrap());
+        assert_eq!(0x1_ffff_0000, CpuSet::from_range(16..=32).as_u64().unwrap());
+        assert_eq!(0b111, CpuSet::from_range(..3).as_u64().unwrap());
+        assert_eq!(0b1111, CpuSet::from_range(..=3).as_u64().unwrap());
+        assert!(CpuSet::from_range(5..5).is_empty());
+    }
+
+    #[test]
+    fn test_set_range_many_words() {
+        let mut cpuset = CpuSet::new(1);
+        cpuset.set_range(60..200);
+        assert_eq!(32, cpuset.byte_size());
+        assert_eq!((60..200).collect::<Vec<_>>(), cpuset.iter().collect::<Vec<_>>());
+        let cpuset = CpuSet::from_range(0..4096);
+        assert_eq!(4096, cpuset.capacity());
+        assert_eq!(4096, cpuset.iter().count());
+    }
+
+    #[test]
+    fn test_set_range_unbounded_end() {
+        let mut cpuset = CpuSet::new(100);
+        cpuset.set_range(120..);
+        assert_eq!("120-127", cpuset.to_string());
+    }
+
+    #[test]
+    fn test_clear_range() {
+        let mut cpuset = CpuSet::all(256);
+        cpuset.clear_range(3..70);
+        cpuset.clear_range(100..=191);
+        cpuset.clear_range(250..1000);
+        assert_eq!(256, cpuset.capacity());
+        assert_eq!("0-2,70-99,192-249", cpuset.to_string());
+        cpuset.clear_range(..);
+        assert!(cpuset.is_empty());
     }
 
     #[test]
     fn test_from_mask_u8() {
-        let cpuset = CpuSet::from_mask(0x3 as u8);
+        let cpuset = CpuSet::from_mask(0x3u8);
         assert_eq!(3, cpuset.as_u64().unwrap());
     }
 
@@ -166,15 +993,73 @@ mod tests {
     fn test_from_mask_u64() {
         let mask: u64 = 1 << 60;
         let cpuset = CpuSet::from_mask::<u64>(mask);
-        assert_eq!(8, cpuset.len());
+        assert_eq!(8, cpuset.byte_size());
         assert_eq!(mask, cpuset.as_u64().unwrap());
     }
 
+    #[test]
+    fn test_from_mask_widths() {
+        assert_eq!(vec![0, 7], CpuSet::from_mask(0x81u8).to_vec());
+        assert_eq!(vec![1, 15], CpuSet::from_mask(0x8002u16).to_vec());
+        assert_eq!(vec![2, 31], CpuSet::from_mask(0x8000_0004u32).to_vec());
+        assert_eq!(vec![3, 63], CpuSet::from_mask(0x8000_0000_0000_0008u64).to_vec());
+        assert_eq!(vec![4], CpuSet::from_mask(0x10usize).to_vec());
+        for cpuset in &[CpuSet::from_mask(0u8), CpuSet::from_mask(0u16), CpuSet::from_mask(0u32)] {
+            assert_eq!(8, cpuset.byte_size());
+        }
+    }
+
+    #[test]
+    fn test_from_mask_u128() {
+        let cpuset = CpuSet::from_mask(1u128 << 127 | 1 << 64 | 1);
+        assert_eq!(16, cpuset.byte_size());
+        assert_eq!(vec![0, 64, 127], cpuset.to_vec());
+    }
+
+    #[test]
+    fn test_from_integers() {
+        assert_eq!(CpuSet::from_mask(0b1011u64), CpuSet::from(0b1011u64));
+        assert_eq!(CpuSet::from_mask(0x8000_0001u32), CpuSet::from(0x8000_0001u32));
+        assert_eq!(vec![0, 1, 3], CpuSet::from(0b1011u64).to_vec());
+    }
+
+    #[test]
+    fn test_from_words() {
+        let words = [0b1011, 0, 1 << 63];
+        let cpuset = CpuSet::from(&words[..]);
+        assert_eq!(24, cpuset.byte_size());
+        assert_eq!(vec![0, 1, 3, 191], cpuset.to_vec());
+        assert_eq!(&words, cpuset.as_slice());
+        assert_eq!(CpuSet::from_mask(1u128 << 64 | 0b10), CpuSet::from(&[0b10, 1][..]));
+        assert!(CpuSet::from(&[][..]).is_empty());
+    }
+
+    #[test]
+    fn test_from_bools() {
+        let mut cpus = vec![false; 100];
+        cpus[1] = true;
+        cpus[64] = true;
+        cpus[99] = true;
+        let cpuset = CpuSet::from_bools(&cpus);
+        assert_eq!(16, cpuset.byte_size());
+        assert_eq!(vec![1, 64, 99], cpuset.to_vec());
+        assert_eq!(cpus, cpuset.to_bools(100));
+        assert!(CpuSet::from_bools(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_to_bools() {
+        let cpuset = CpuSet::from_mask(0b101u8);
+        assert_eq!(vec![true, false, true, false], cpuset.to_bools(4));
+        assert_eq!(vec![true], cpuset.to_bools(1));
+        assert_eq!(vec![false; 70], CpuSet::new(0).to_bools(70));
+    }
+
     #[test]
     fn test_single_low() {
         let mask: u64 = 1 << 3;
         let cpuset = CpuSet::single(3);
-        assert_eq!(MASK_BITS / 8, cpuset.len());
+        assert_eq!(MASK_BITS / 8, cpuset.byte_size());
         assert_eq!(mask, cpuset.as_u64().unwrap());
     }
 
@@ -203,9 +1088,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_set_grows_one_word() {
+        let mut cpuset = CpuSet::new(1);
+        cpuset.set(64);
+        assert_eq!(16, cpuset.byte_size());
+        assert_eq!(&[0, 1], cpuset.as_slice());
+    }
+
+    #[test]
+    fn test_set_grows_many_words() {
+        let mut cpuset = CpuSet::new(1);
+        cpuset.set(0);
+        cpuset.set(200);
+        assert_eq!(32, cpuset.byte_size());
+        assert_eq!(&[1, 0, 0, 1 << 8], cpuset.as_slice());
+    }
+
     #[test]
     fn test_clear_and_is_set() {
-        let mut cpuset = CpuSet::from_mask(::std::u64::MAX);
+        let mut cpuset = CpuSet::from_mask(u64::MAX);
         let max = 64;
         for i in 0..max {
             for j in 0..max {
@@ -218,17 +1120,563 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut cpuset = CpuSet::new(256);
+        cpuset.shrink_to_fit();
+        assert_eq!(8, cpuset.byte_size());
+        let mut cpuset = CpuSet::new(256);
+        cpuset.set(70);
+        let unshrunk = cpuset.clone();
+        cpuset.shrink_to_fit();
+        assert_eq!(16, cpuset.byte_size());
+        assert_eq!(unshrunk, cpuset);
+    }
+
+    #[test]
+    fn test_shrink_to_fit_after_and() {
+        let mut cpuset: CpuSet = vec![1, 200].into_iter().collect();
+        cpuset &= &CpuSet::single(1);
+        assert_eq!(32, cpuset.byte_size());
+        cpuset.shrink_to_fit();
+        assert_eq!(8, cpuset.byte_size());
+        assert_eq!(2, cpuset.as_u64().unwrap());
+    }
+
+    #[test]
+    fn test_set_affinity_after_shrink() {
+        let mut cpuset = CpuSet::new(1024);
+        cpuset.set(0);
+        cpuset.shrink_to_fit();
+        cpuset.set_affinity(0).unwrap();
+        assert_eq!(cpuset, CpuSet::get_affinity(0, 1024).unwrap());
+    }
+
+    #[test]
+    fn test_inline_storage() {
+        fn is_inline(cpuset: &CpuSet) -> bool {
+            match cpuset.mask {
+                Words::Inline { .. } => true,
+                Words::Heap(_) => false,
+            }
+        }
+        let mut cpuset = CpuSet::new(128);
+        assert!(is_inline(&cpuset));
+        cpuset.set(127);
+        assert!(is_inline(&cpuset));
+        cpuset.set(128);
+        assert!(!is_inline(&cpuset));
+        assert_eq!(vec![127, 128], cpuset.to_vec());
+        cpuset.clear(128);
+        cpuset.shrink_to_fit();
+        assert!(is_inline(&cpuset));
+        assert_eq!(vec![127], cpuset.to_vec());
+        assert!(!is_inline(&CpuSet::new(129)));
+    }
+
+    #[test]
+    fn test_clear_twice() {
+        let mut cpuset = CpuSet::from_mask(0b1111u8);
+        cpuset.clear(3);
+        cpuset.clear(3);
+        assert_eq!(0b0111, cpuset.as_u64().unwrap());
+    }
+
+    #[test]
+    fn test_clear_unset() {
+        let mut cpuset = CpuSet::single(1);
+        cpuset.clear(3);
+        cpuset.clear(70);
+        assert!(!cpuset.is_set(3));
+        assert_eq!(0b10, cpuset.as_u64().unwrap());
+    }
+
     #[test]
     fn test_is_set_too_large() {
-        let cpuset = CpuSet::from_mask(0b11111111);
+        let cpuset = CpuSet::from_mask(0b11111111u8);
         assert!(!cpuset.is_set(9));
         assert!(!cpuset.is_set(10000));
     }
 
+    #[test]
+    fn test_is_set_word_boundary() {
+        let cpuset = CpuSet::new(1);
+        for &cpu in &[63, 64, 65, 127, 128, 511, 512] {
+            assert!(!cpuset.is_set(cpu));
+        }
+        let cpuset = CpuSet::single(64);
+        assert!(cpuset.is_set(64));
+        assert!(!cpuset.is_set(127));
+        assert!(!cpuset.is_set(128));
+    }
+
     #[test]
     fn test_as_u64_too_large_set() {
-        let cpuset = CpuSet::new(80);
+        let mut cpuset = CpuSet::new(80);
+        cpuset.set(70);
         assert!(cpuset.as_u64().is_err());
+        let error = cpuset.as_u64().unwrap_err();
+        assert_eq!((64, 70), (error.width(), error.cpu()));
+    }
+
+    #[test]
+    fn test_as_u64_large_set_low_cpus() {
+        let mut cpuset = CpuSet::new(80);
+        assert_eq!(0, cpuset.as_u64().unwrap());
+        cpuset.set(3);
+        cpuset.set(63);
+        assert_eq!(16, cpuset.byte_size());
+        assert_eq!(1 << 63 | 1 << 3, cpuset.as_u64().unwrap());
+    }
+
+    #[test]
+    fn test_first_last_set() {
+        assert_eq!(None, CpuSet::new(256).first_set());
+        assert_eq!(None, CpuSet::new(256).last_set());
+        let cpuset = CpuSet::single(5);
+        assert_eq!(Some(5), cpuset.first_set());
+        assert_eq!(Some(5), cpuset.last_set());
+        let cpuset: CpuSet = vec![63, 64, 150].into_iter().collect();
+        assert_eq!(Some(63), cpuset.first_set());
+        assert_eq!(Some(150), cpuset.last_set());
+        let mut cpuset = CpuSet::new(300);
+        cpuset.set(0);
+        cpuset.set(191);
+        assert_eq!(Some(0), cpuset.first_set());
+        assert_eq!(Some(191), cpuset.last_set());
+    }
+
+    #[test]
+    fn test_to_vec_from_slice() {
+        let cpus = vec![0, 5, 64, 127, 128, 190];
+        let cpuset = CpuSet::from_slice(&cpus);
+        assert_eq!(24, cpuset.byte_size());
+        assert_eq!(cpus, cpuset.to_vec());
+        assert_eq!(cpus, Vec::from(&cpuset));
+        assert_eq!(cpuset, CpuSet::from_slice(&cpuset.to_vec()));
+        assert!(CpuSet::new(128).to_vec().is_empty());
+    }
+
+    #[test]
+    fn test_as_slice() {
+        let mut cpuset: CpuSet = vec![0, 3, 64, 130].into_iter().collect();
+        assert_eq!(&[0b1001, 1, 1 << 2], cpuset.as_slice());
+        cpuset.as_mut_slice()[1] = 0b110;
+        assert_eq!(vec![0, 3, 65, 66, 130], cpuset.to_vec());
+    }
+
+    #[test]
+    fn test_distribute() {
+        let cpuset: CpuSet = (0..6).chain(62..66).collect();
+        let sets = cpuset.distribute(3).unwrap();
+        assert_eq!(vec![vec![0, 1, 2, 3], vec![4, 5, 62], vec![63, 64, 65]],
+                   sets.iter().map(CpuSet::to_vec).collect::<Vec<_>>());
+        let union = sets.iter().fold(CpuSet::new(0), |union, set| &union | set);
+        assert_eq!(cpuset, union);
+        for (i, a) in sets.iter().enumerate() {
+            for b in &sets[i + 1..] {
+                assert!((a & b).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_distribute_more_sets_than_cpus() {
+        let sets = CpuSet::from_slice(&[1, 5]).distribute(4).unwrap();
+        assert_eq!(vec![vec![1], vec![5], vec![], vec![]],
+                   sets.iter().map(CpuSet::to_vec).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_distribute_zero() {
+        assert!(CpuSet::all(4).distribute(0).is_err());
+    }
+
+    #[test]
+    fn test_take_first() {
+        let cpuset: CpuSet = (0..4).chain(62..66).collect();
+        assert_eq!(CpuSet::from_slice(&[0, 1, 2]), cpuset.take_first(3));
+        assert_eq!(vec![0, 1, 2, 3, 62, 63], cpuset.take_first(6).to_vec());
+        assert!(cpuset.take_first(0).is_empty());
+        assert_eq!(cpuset, cpuset.take_first(100));
+        assert!(CpuSet::new(64).take_first(2).is_empty());
+    }
+
+    #[test]
+    fn test_take_last() {
+        let cpuset: CpuSet = (0..4).chain(62..66).collect();
+        assert_eq!(CpuSet::from_slice(&[63, 64, 65]), cpuset.take_last(3));
+        assert!(cpuset.take_last(0).is_empty());
+        assert_eq!(cpuset, cpuset.take_last(100));
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(None, CpuSet::new(64).ranges().next());
+        let cpuset: CpuSet = vec![0, 2, 3, 4, 9].into_iter().collect();
+        assert_eq!(vec![0..=0, 2..=4, 9..=9], cpuset.ranges().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ranges_across_words() {
+        let cpuset: CpuSet = (60..130).chain(191..193).collect();
+        assert_eq!(vec![60..=129, 191..=192], cpuset.ranges().collect::<Vec<_>>());
+        assert_eq!(vec![0..=255], CpuSet::all(256).ranges().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_empty() {
+        let cpuset = CpuSet::new(128);
+        assert_eq!(None, cpuset.iter().next());
+    }
+
+    #[test]
+    fn test_iter_single_word() {
+        let cpuset = CpuSet::from_mask(0b1010_0101u8);
+        assert_eq!(vec![0, 2, 5, 7], cpuset.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_many_words() {
+        let mut cpuset = CpuSet::new(200);
+        for &cpu in &[0, 63, 64, 130, 199] {
+            cpuset.set(cpu);
+        }
+        let cpus: Vec<usize> = (&cpuset).into_iter().collect();
+        assert_eq!(vec![0, 63, 64, 130, 199], cpus);
+    }
+
+    #[test]
+    fn test_collect() {
+        let cpuset: CpuSet = [0, 2, 4, 6].iter().cloned().collect();
+        assert_eq!(0b0101_0101, cpuset.as_u64().unwrap());
+    }
+
+    #[test]
+    fn test_collect_many_words() {
+        let cpuset: CpuSet = vec![1, 65, 190].into_iter().collect();
+        assert_eq!(24, cpuset.byte_size());
+        assert_eq!(vec![1, 65, 190], cpuset.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut cpuset = CpuSet::single(3);
+        cpuset.extend(vec![70, 5]);
+        assert_eq!(16, cpuset.byte_size());
+        assert_eq!(vec![3, 5, 70], cpuset.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut cpuset = CpuSet::new(8);
+        cpuset.set(5);
+        assert!(cpuset == CpuSet::single(5));
+        assert!(cpuset == CpuSet::from_mask(1u8 << 5));
+        assert!(CpuSet::new(0) == CpuSet::new(64));
+        assert!(cpuset != CpuSet::single(4));
+    }
+
+    #[test]
+    fn test_eq_different_sizes() {
+        let mut large = CpuSet::new(256);
+        large.set(1);
+        large.set(3);
+        let small = CpuSet::from_mask(0b1010u64);
+        assert!(large == small);
+        assert!(small == large);
+        large.set(200);
+        assert!(large != small);
+        assert!(small != large);
+    }
+
+    #[test]
+    fn test_bit_ops() {
+        let a = CpuSet::from_mask(0b1100u8);
+        let b = CpuSet::from_mask(0b1010u8);
+        assert_eq!(0b1000, (&a & &b).as_u64().unwrap());
+        assert_eq!(0b1110, (&a | &b).as_u64().unwrap());
+        assert_eq!(0b0110, (&a ^ &b).as_u64().unwrap());
+    }
+
+    #[test]
+    fn test_bit_ops_different_sizes() {
+        let wide: CpuSet = vec![1, 2, 100].into_iter().collect();
+        let narrow = CpuSet::from_mask(0b0110u8);
+        for &(lhs, rhs) in &[(&wide, &narrow), (&narrow, &wide)] {
+            let and = lhs & rhs;
+            assert_eq!(16, and.byte_size());
+            assert_eq!(vec![1, 2], and.iter().collect::<Vec<_>>());
+            let or = lhs | rhs;
+            assert_eq!(16, or.byte_size());
+            assert_eq!(vec![1, 2, 100], or.iter().collect::<Vec<_>>());
+            let xor = lhs ^ rhs;
+            assert_eq!(16, xor.byte_size());
+            assert_eq!(vec![100], xor.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_bit_ops_assign() {
+        let mut cpuset = CpuSet::single(0);
+        cpuset |= &CpuSet::single(70);
+        assert_eq!(vec![0, 70], cpuset.iter().collect::<Vec<_>>());
+        cpuset ^= &CpuSet::from_mask(0b11u8);
+        assert_eq!(vec![1, 70], cpuset.iter().collect::<Vec<_>>());
+        cpuset &= &CpuSet::single(1);
+        assert_eq!(vec![1], cpuset.iter().collect::<Vec<_>>());
+        assert_eq!(16, cpuset.byte_size());
+    }
+
+    #[test]
+    fn test_difference() {
+        let allowed = CpuSet::from_mask(0b1111u8);
+        let reserved = CpuSet::from_mask(0b0101u8);
+        assert_eq!(0b1010, allowed.difference(&reserved).as_u64().unwrap());
+        assert_eq!(0, reserved.difference(&allowed).as_u64().unwrap());
+    }
+
+    #[test]
+    fn test_difference_different_sizes() {
+        let small = CpuSet::from_mask(0b0110u8);
+        let large: CpuSet = vec![1, 100].into_iter().collect();
+        assert_eq!(vec![2], small.difference(&large).iter().collect::<Vec<_>>());
+        assert_eq!(vec![100], large.difference(&small).iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let mut cpuset: CpuSet = vec![0, 1, 64, 65].into_iter().collect();
+        cpuset.remove_all(&CpuSet::single(65));
+        cpuset.remove_all(&CpuSet::single(300));
+        assert_eq!(vec![0, 1, 64], cpuset.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_is_subset() {
+        let small = CpuSet::from_mask(0b0110u8);
+        let large = CpuSet::from_mask(0b1110u8);
+        assert!(small.is_subset(&large));
+        assert!(large.is_superset(&small));
+        assert!(!large.is_subset(&small));
+        assert!(!small.is_superset(&large));
+        assert!(small.is_subset(&small));
+    }
+
+    #[test]
+    fn test_is_subset_empty() {
+        let empty = CpuSet::new(0);
+        assert!(empty.is_subset(&CpuSet::single(100)));
+        assert!(empty.is_subset(&CpuSet::new(256)));
+        assert!(CpuSet::new(256).is_subset(&empty));
+        assert!(!CpuSet::single(0).is_subset(&empty));
+    }
+
+    #[test]
+    fn test_is_subset_across_words() {
+        let allowed: CpuSet = vec![0, 63, 64, 65].into_iter().collect();
+        let pinned: CpuSet = vec![63, 64].into_iter().collect();
+        assert!(pinned.is_subset(&allowed));
+        assert!(allowed.is_superset(&pinned));
+        assert!(!CpuSet::single(66).is_subset(&allowed));
+        assert!(!CpuSet::single(130).is_subset(&allowed));
+        assert!(!allowed.is_subset(&CpuSet::from_mask(u64::MAX)));
+    }
+
+    #[test]
+    fn test_clone() {
+        let cpuset: CpuSet = vec![2, 90].into_iter().collect();
+        let mut copy = cpuset.clone();
+        assert_eq!(cpuset, copy);
+        copy.clear(90);
+        assert_eq!(vec![2, 90], cpuset.iter().collect::<Vec<_>>());
+        assert_eq!(vec![2], copy.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_debug() {
+        assert_eq!("CpuSet {}", format!("{:?}", CpuSet::new(8)));
+        assert_eq!("CpuSet {3}", format!("{:?}", CpuSet::single(3)));
+        let cpuset: CpuSet = vec![0, 2, 5, 6, 7, 8, 62, 63, 64, 65, 200].into_iter().collect();
+        assert_eq!("CpuSet {0, 2, 5-8, 62-65, 200}", format!("{:?}", cpuset));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("", CpuSet::new(64).to_string());
+        assert_eq!("7", CpuSet::single(7).to_string());
+        assert_eq!("0-3,8,10-11", CpuSet::from_mask(0b1101_0000_1111u16).to_string());
+    }
+
+    #[test]
+    fn test_display_many_words() {
+        let cpuset: CpuSet = (60..70).chain(128..192).chain(vec![300]).collect();
+        assert_eq!("60-69,128-191,300", cpuset.to_string());
+    }
+
+    #[test]
+    fn test_from_str() {
+        let cpuset: CpuSet = "0-3,7,16-23".parse().unwrap();
+        assert_eq!(0x00ff_008f, cpuset.as_u64().unwrap());
+        let cpuset: CpuSet = " 1 , 4 - 5\n".parse().unwrap();
+        assert_eq!(vec![1, 4, 5], cpuset.iter().collect::<Vec<_>>());
+        let cpuset: CpuSet = "70".parse().unwrap();
+        assert_eq!(CpuSet::single(70), cpuset);
+    }
+
+    #[test]
+    fn test_from_str_empty() {
+        assert_eq!(CpuSet::new(0), "".parse().unwrap());
+        assert_eq!(CpuSet::new(0), "\n".parse().unwrap());
+    }
+
+    #[test]
+    fn test_from_str_stride() {
+        let cpuset: CpuSet = "0-10:2,64-70:3".parse().unwrap();
+        assert_eq!(vec![0, 2, 4, 6, 8, 10, 64, 67, 70], cpuset.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        for input in &["3-1", "a", "1,,2", "1,", "-", "1-", "0-3:0", "4:2", "0-3:x", "1.5"] {
+            assert!(input.parse::<CpuSet>().is_err(), "{} parsed", input);
+        }
+        let error = "0-3,5-2".parse::<CpuSet>().unwrap_err();
+        assert_eq!("invalid CPU set component `5-2`: range start is greater than range end",
+                   error.to_string());
+    }
+
+    #[test]
+    fn test_from_str_display_round_trip() {
+        let cpuset: CpuSet = (60..70).chain(128..192).chain(vec![0, 300]).collect();
+        assert_eq!(cpuset, cpuset.to_string().parse().unwrap());
+        let list = "0-3,8,10-11,64-127,255";
+        assert_eq!(list, list.parse::<CpuSet>().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_from_hex_mask() {
+        assert_eq!(CpuSet::from_mask(0xfu8), CpuSet::from_hex_mask("f").unwrap());
+        assert_eq!(CpuSet::from_mask(0x3_0000_0101u64),
+                   CpuSet::from_hex_mask("00000003,00000101\n").unwrap());
+        assert_eq!(CpuSet::new(0), CpuSet::from_hex_mask("00000000").unwrap());
+    }
+
+    #[test]
+    fn test_from_hex_mask_128_cpus() {
+        let all = CpuSet::from_hex_mask("ffffffff,ffffffff,ffffffff,ffffffff").unwrap();
+        assert_eq!((0..128).collect::<Vec<_>>(), all.iter().collect::<Vec<_>>());
+        let siblings = CpuSet::from_hex_mask("00000000,00000001,00000000,00000001").unwrap();
+        assert_eq!(vec![0, 64], siblings.iter().collect::<Vec<_>>());
+        let node = CpuSet::from_hex_mask("ffff0000,00000000,ffff0000,00000000").unwrap();
+        assert_eq!("48-63,112-127", node.to_string());
+    }
+
+    #[test]
+    fn test_from_hex_mask_invalid() {
+        for input in &["", "0x1", "f,", ",f", "g", "123456789", "+f", "ff ff"] {
+            assert!(CpuSet::from_hex_mask(input).is_err(), "{} parsed", input);
+        }
+    }
+
+    #[test]
+    fn test_to_hex_mask_string() {
+        assert_eq!("00000000,00000000", CpuSet::new(1).to_hex_mask_string());
+        assert_eq!("00000001,00000000", CpuSet::single(32).to_hex_mask_string());
+        for mask in &["ffffffff,ffffffff,ffffffff,ffffffff", "00000000,00000001,00000000,00000001"] {
+            assert_eq!(*mask, CpuSet::from_hex_mask(mask).unwrap().to_hex_mask_string());
+        }
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        assert_eq!(vec![0x05, 0, 0, 0, 0, 0, 0, 0], CpuSet::from_slice(&[0, 2]).to_bytes());
+        let bytes = CpuSet::from_slice(&[9, 64]).to_bytes();
+        assert_eq!(16, bytes.len());
+        assert_eq!(0x02, bytes[1]);
+        assert_eq!(0x01, bytes[8]);
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        assert_eq!(CpuSet::from_slice(&[0, 2, 9]), CpuSet::from_bytes(&[0x05, 0x02]));
+        assert_eq!(CpuSet::new(0), CpuSet::from_bytes(&[]));
+        assert_eq!(128, CpuSet::from_bytes(&[0; 9]).capacity());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        for &num_cpus in &[1, 64, 65, 300] {
+            let cpuset = CpuSet::all(num_cpus);
+            let decoded = CpuSet::from_bytes(&cpuset.to_bytes());
+            assert_eq!(cpuset, decoded);
+            assert_eq!(cpuset.capacity(), decoded.capacity());
+            let sparse: CpuSet = (0..num_cpus).filter(|cpu| cpu % 3 == 0).collect();
+            assert_eq!(sparse, CpuSet::from_bytes(&sparse.to_bytes()));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let cpuset: CpuSet = vec![0, 1, 2, 3, 8, 100].into_iter().collect();
+        let json = ::serde_json::to_string(&cpuset).unwrap();
+        assert_eq!("\"0-3,8,100\"", json);
+        assert_eq!(cpuset, ::serde_json::from_str::<CpuSet>(&json).unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_deserialize_sequence() {
+        let cpuset: CpuSet = ::serde_json::from_str("[4, 2, 70]").unwrap();
+        assert_eq!(vec![2, 4, 70], cpuset.iter().collect::<Vec<_>>());
+        assert!(::serde_json::from_str::<CpuSet>("\"3-1\"").is_err());
+        assert!(::serde_json::from_str::<CpuSet>("[-1]").is_err());
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(64, CpuSet::new(0).capacity());
+        assert_eq!(64, CpuSet::new(64).capacity());
+        assert_eq!(128, CpuSet::new(65).capacity());
+        assert_eq!(192, CpuSet::single(150).capacity());
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(CpuSet::new(0).is_empty());
+        assert!(CpuSet::new(256).is_empty());
+        assert!(!CpuSet::single(0).is_empty());
+        assert!(!CpuSet::single(200).is_empty());
+        let mut cpuset = CpuSet::single(100);
+        cpuset.clear(100);
+        assert!(cpuset.is_empty());
+    }
+
+    #[test]
+    fn test_as_u128() {
+        let mut cpuset = CpuSet::new(128);
+        cpuset.set(100);
+        cpuset.set(3);
+        assert_eq!(1 << 100 | 1 << 3, cpuset.as_u128().unwrap());
+        assert_eq!(0b1000, CpuSet::single(3).as_u128().unwrap());
+        let error = CpuSet::single(128).as_u128().unwrap_err();
+        assert_eq!(128, error.width());
+        assert_eq!(128, error.cpu());
+    }
+
+    #[test]
+    fn test_try_from() {
+        let mut cpuset = CpuSet::new(128);
+        cpuset.set(100);
+        assert_eq!(1 << 100, u128::try_from(&cpuset).unwrap());
+        let error = u64::try_from(&cpuset).unwrap_err();
+        assert_eq!("CPU 100 does not fit in a 64 bit integer", error.to_string());
+        cpuset.clear(100);
+        cpuset.set(63);
+        assert_eq!(1 << 63, u64::try_from(&cpuset).unwrap());
+        assert_eq!(1 << 63, u128::try_from(&cpuset).unwrap());
+        assert_eq!(0b101, usize::try_from(&CpuSet::from_mask(0b101u8)).unwrap());
     }
 
     #[test]
@@ -0,0 +1,211 @@
+//! Error type for the fallible operations of this crate
+use std::error;
+use std::fmt;
+use std::io;
+use libc::{EACCES, EINVAL, ENOSYS, EOPNOTSUPP, EPERM, ESRCH};
+#[cfg(target_os = "linux")]
+use config::ConfigStep;
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+use cpuset::{CpuSet, ParseCpuSetError};
+
+/// Errors returned by the operations of this crate
+///
+/// Failed calls to the system capture `errno` immediately, see `raw_os_error` and `kind`.
+#[derive(Debug)]
+pub enum Error {
+    /// A call to the system failed, or reading or writing a system file failed
+    Io(io::Error),
+    /// A system file did not contain a valid CPU set
+    #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+    Parse(ParseCpuSetError),
+    /// The NUMA node does not exist
+    NoSuchNode(usize),
+    /// An argument was rejected before making any call to the system
+    InvalidArgument(String),
+    /// The kernel reported a scheduling policy this crate does not know
+    UnknownPolicy(i32),
+    /// The system applied a different value than requested, e.g. a nice value out of range
+    Clamped {
+        /// The value that was requested
+        requested: i32,
+        /// The value that was applied
+        actual: i32,
+    },
+    /// The kernel applied a smaller affinity than requested, e.g. because of the cgroup cpuset
+    #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+    AffinityRestricted {
+        /// The affinity that was requested
+        requested: CpuSet,
+        /// The affinity the kernel applied
+        effective: CpuSet,
+    },
+    /// Applying a `SchedulerConfig` failed, see `SchedulerConfig::apply_to`
+    #[cfg(target_os = "linux")]
+    ApplyFailed {
+        /// The step that failed
+        step: ConfigStep,
+        /// Why the step failed
+        error: Box<Error>,
+        /// Why restoring the steps already applied failed, `None` if they were restored
+        rollback: Option<Box<Error>>,
+    },
+}
+
+/// General categories of `Error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The caller lacks the privileges for the operation, e.g. `CAP_SYS_NICE` (`EPERM`, `EACCES`)
+    PermissionDenied,
+    /// The process or thread does not exist (`ESRCH`)
+    NoSuchProcess,
+    /// An argument was rejected, e.g. an empty mask or a priority out of range (`EINVAL`)
+    InvalidArgument,
+    /// The operation is not supported by this kernel (`ENOSYS`, `EOPNOTSUPP`)
+    Unsupported,
+    /// Any other error
+    Other,
+}
+
+impl Error {
+    /// Create an `Error` from the `errno` of the last failed call to the system.
+    pub(crate) fn last_os_error() -> Error {
+        Error::Io(io::Error::last_os_error())
+    }
+
+    /// Create an `Error` from a raw `errno` value.
+    pub(crate) fn from_raw_os_error(code: i32) -> Error {
+        Error::Io(io::Error::from_raw_os_error(code))
+    }
+
+    /// Get the `errno` of the failed call to the system, if this error was caused by one.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            Error::Io(ref error) => error.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// Get the general category of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::Io(ref error) => match error.raw_os_error() {
+                Some(EPERM) | Some(EACCES) => ErrorKind::PermissionDenied,
+                Some(ESRCH) => ErrorKind::NoSuchProcess,
+                Some(EINVAL) => ErrorKind::InvalidArgument,
+                Some(ENOSYS) | Some(EOPNOTSUPP) => ErrorKind::Unsupported,
+                Some(_) => ErrorKind::Other,
+                None => match error.kind() {
+                    io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+                    io::ErrorKind::InvalidInput => ErrorKind::InvalidArgument,
+                    io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+                    _ => ErrorKind::Other,
+                },
+            },
+            Error::NoSuchNode(_) | Error::InvalidArgument(_) => ErrorKind::InvalidArgument,
+            #[cfg(target_os = "linux")]
+            Error::ApplyFailed { ref error, .. } => error.kind(),
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => error.fmt(f),
+            #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+            Error::Parse(ref error) => error.fmt(f),
+            Error::NoSuchNode(node) => write!(f, "NUMA node {} does not exist", node),
+            Error::InvalidArgument(ref message) => write!(f, "invalid argument: {}", message),
+            Error::UnknownPolicy(policy) => write!(f, "unknown scheduling policy {}", policy),
+            Error::Clamped { requested, actual } => {
+                write!(f, "requested value {} was clamped to {}", requested, actual)
+            }
+            #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+            Error::AffinityRestricted { ref requested, ref effective } => {
+                write!(f, "requested affinity {} was restricted to {}", requested, effective)
+            }
+            #[cfg(target_os = "linux")]
+            Error::ApplyFailed { step, ref error, ref rollback } => {
+                write!(f, "setting the {} failed: {}", step, error)?;
+                match *rollback {
+                    Some(ref rollback) => write!(f, ", restoring the previous settings failed: {}",
+                                                 rollback),
+                    None => write!(f, ", the previous settings were restored"),
+                }
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+            Error::Parse(ref error) => Some(error),
+            Error::NoSuchNode(_) | Error::InvalidArgument(_) | Error::UnknownPolicy(_) |
+            Error::Clamped { .. } => None,
+            #[cfg(any(target_os = "linux", target_os = "emscripten"))]
+            Error::AffinityRestricted { .. } => None,
+            #[cfg(target_os = "linux")]
+            Error::ApplyFailed { ref error, .. } => Some(&**error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+impl From<ParseCpuSetError> for Error {
+    fn from(error: ParseCpuSetError) -> Error {
+        Error::Parse(error)
+    }
+}
+
+/// Keeps the raw OS error code for errors caused by a failed call to the system.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        let kind = match error.kind() {
+            ErrorKind::PermissionDenied => io::ErrorKind::PermissionDenied,
+            ErrorKind::InvalidArgument => io::ErrorKind::InvalidInput,
+            ErrorKind::Unsupported => io::ErrorKind::Unsupported,
+            ErrorKind::NoSuchProcess | ErrorKind::Other => io::ErrorKind::Other,
+        };
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(kind, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use libc::{EINVAL, ENOSYS, EPERM, ESRCH};
+    use super::{Error, ErrorKind};
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(ErrorKind::PermissionDenied, Error::from_raw_os_error(EPERM).kind());
+        assert_eq!(ErrorKind::NoSuchProcess, Error::from_raw_os_error(ESRCH).kind());
+        assert_eq!(ErrorKind::InvalidArgument, Error::from_raw_os_error(EINVAL).kind());
+        assert_eq!(ErrorKind::Unsupported, Error::from_raw_os_error(ENOSYS).kind());
+        assert_eq!(ErrorKind::InvalidArgument, Error::InvalidArgument("n".into()).kind());
+        assert_eq!(ErrorKind::Other, Error::UnknownPolicy(42).kind());
+        assert_eq!(ErrorKind::Other, Error::Clamped { requested: -15, actual: -5 }.kind());
+    }
+
+    #[test]
+    fn test_into_io_error() {
+        let error = io::Error::from(Error::from_raw_os_error(ESRCH));
+        assert_eq!(Some(ESRCH), error.raw_os_error());
+        let error = io::Error::from(Error::InvalidArgument("n".into()));
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        assert_eq!(None, error.raw_os_error());
+    }
+}
@@ -0,0 +1,368 @@
+//! Guards restoring the scheduling of the calling thread when they are dropped
+use std::marker::PhantomData;
+#[cfg(target_os = "linux")]
+use cpuset::CpuSet;
+use error::Error;
+use resource::{get_self_priority, set_self_priority, Which};
+#[cfg(target_os = "linux")]
+use sched::{get_self_affinity_auto, set_current_thread_affinity};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use sched::{get_self_policy_and_priority, set_current_thread_policy, set_policy_unchecked,
+            Policy};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use sched_attr::{get_self_sched_attr, set_self_deadline_policy_with_flags, DeadlineParams,
+                 SchedFlags};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use target::Target;
+
+/// Restores the previous scheduling policy and priority of the calling thread when dropped.
+///
+/// Created with `PolicyGuard::elevate`. The guard can not be sent to another thread, as it
+/// restores the policy of the thread that created it. Failing to restore the policy in `drop`
+/// is ignored, use `restore` to handle the error.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Debug)]
+pub struct PolicyGuard {
+    policy: Policy,
+    priority: i32,
+    deadline: Option<(DeadlineParams, SchedFlags)>,
+    restored: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl PolicyGuard {
+    /// Set the scheduling policy of the calling thread until the guard is dropped.
+    ///
+    /// The current policy and priority are saved first, including the parameters of
+    /// `Policy::Deadline`. The new policy is set like with `set_policy`.
+    pub fn elevate(policy: Policy, priority: i32) -> Result<PolicyGuard, Error> {
+        let (previous, previous_priority) = get_self_policy_and_priority()?;
+        let deadline = match previous {
+            Policy::Deadline => {
+                let attr = get_self_sched_attr()?;
+                attr.deadline.map(|params| (params, attr.flags))
+            }
+            _ => None,
+        };
+        set_current_thread_policy(policy, priority)?;
+        Ok(PolicyGuard {
+            policy: previous,
+            priority: previous_priority,
+            deadline,
+            restored: false,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Restore the previous policy and priority now, returning any error.
+    pub fn restore(mut self) -> Result<(), Error> {
+        self.restored = true;
+        self.restore_previous()
+    }
+
+    fn restore_previous(&self) -> Result<(), Error> {
+        match self.deadline {
+            Some((ref params, flags)) => set_self_deadline_policy_with_flags(params, flags),
+            None => set_policy_unchecked(Target::CurrentThread, self.policy, self.priority),
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Drop for PolicyGuard {
+    fn drop(&mut self) {
+        if !self.restored {
+            let _ = self.restore_previous();
+        }
+    }
+}
+
+/// Run `f` with the scheduling policy of the calling thread set to `policy` and `priority`.
+///
+/// The previous policy is restored afterwards, also when `f` panics. See `PolicyGuard`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn with_policy<F, R>(policy: Policy, priority: i32, f: F) -> Result<R, Error>
+    where F: FnOnce() -> R
+{
+    let guard = PolicyGuard::elevate(policy, priority)?;
+    let result = f();
+    guard.restore()?;
+    Ok(result)
+}
+
+/// Restores the previous cpu affinity of the calling thread when dropped.
+///
+/// Created with `AffinityGuard::pin`. Nested guards restore the affinity in reverse order. The
+/// guard can not be sent to another thread, as it restores the affinity of the thread that
+/// created it. Failing to restore the affinity in `drop` is ignored, use `restore` to handle the
+/// error.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct AffinityGuard {
+    previous: CpuSet,
+    restored: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+#[cfg(target_os = "linux")]
+impl AffinityGuard {
+    /// Set the cpu affinity of the calling thread to `cpuset` until the guard is dropped.
+    /// The current affinity is saved first, see `get_affinity_auto`.
+    pub fn pin(cpuset: &CpuSet) -> Result<AffinityGuard, Error> {
+        let previous = get_self_affinity_auto()?;
+        set_current_thread_affinity(cpuset)?;
+        Ok(AffinityGuard {
+            previous,
+            restored: false,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Get the affinity that is restored.
+    pub fn previous(&self) -> &CpuSet {
+        &self.previous
+    }
+
+    /// Restore the previous affinity now, returning any error.
+    pub fn restore(mut self) -> Result<(), Error> {
+        self.restored = true;
+        set_current_thread_affinity(&self.previous)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for AffinityGuard {
+    fn drop(&mut self) {
+        if !self.restored {
+            let _ = set_current_thread_affinity(&self.previous);
+        }
+    }
+}
+
+/// Run `f` with the cpu affinity of the calling thread set to `cpuset`.
+///
+/// The previous affinity is restored afterwards, also when `f` panics. See `AffinityGuard`.
+#[cfg(target_os = "linux")]
+pub fn with_affinity<F, R>(cpuset: &CpuSet, f: F) -> Result<R, Error>
+    where F: FnOnce() -> R
+{
+    let guard = AffinityGuard::pin(cpuset)?;
+    let result = f();
+    guard.restore()?;
+    Ok(result)
+}
+
+/// Restores the previous scheduling priority of the calling process when dropped, on Linux of
+/// the calling thread.
+///
+/// Created with `PriorityGuard::set`. Raising the priority back, by lowering the nice value,
+/// requires `CAP_SYS_NICE` or a sufficient `RLIMIT_NICE`, see `allowed_nice_range`. Failing to
+/// restore the priority in `drop` is ignored, use `restore` to handle the error. The guard can
+/// not be sent to another thread.
+#[derive(Debug)]
+pub struct PriorityGuard {
+    which: Which,
+    priority: i32,
+    restored: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl PriorityGuard {
+    /// Set the scheduling priority for the `Which` of the calling process until the guard is
+    /// dropped, see `set_self_priority`.
+    ///
+    /// The current priority is saved first with `get_self_priority`. For `Which::Group` and
+    /// `Which::User` that is the highest priority of the processes, which is restored to all of
+    /// them.
+    pub fn set<P: Into<i32>>(which: Which, priority: P) -> Result<PriorityGuard, Error> {
+        let previous = get_self_priority(which)?;
+        set_self_priority(which, priority)?;
+        Ok(PriorityGuard {
+            which,
+            priority: previous,
+            restored: false,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Get the priority that is restored.
+    pub fn previous(&self) -> i32 {
+        self.priority
+    }
+
+    /// Restore the previous priority now, returning any error.
+    pub fn restore(mut self) -> Result<(), Error> {
+        self.restored = true;
+        set_self_priority(self.which, self.priority)
+    }
+}
+
+impl Drop for PriorityGuard {
+    fn drop(&mut self) {
+        if !self.restored {
+            let _ = set_self_priority(self.which, self.priority);
+        }
+    }
+}
+
+/// Run `f` with the scheduling priority for the `Which` of the calling process set to
+/// `priority`.
+///
+/// The previous priority is restored afterwards, also when `f` panics. See `PriorityGuard`.
+pub fn with_priority<P, F, R>(which: Which, priority: P, f: F) -> Result<R, Error>
+    where P: Into<i32>,
+          F: FnOnce() -> R
+{
+    let guard = PriorityGuard::set(which, priority)?;
+    let result = f();
+    guard.restore()?;
+    Ok(result)
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+    use std::panic;
+    use std::thread;
+    use super::{with_affinity, with_policy, with_priority, AffinityGuard, PolicyGuard,
+                PriorityGuard};
+    use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+    use realtime::allowed_nice_range;
+    use resource::{get_self_priority, Nice, Which};
+    use sched::{get_self_affinity_auto, get_self_policy, get_self_policy_and_priority,
+                set_self_affinity, set_self_policy, Policy};
+
+    #[test]
+    fn test_policy_guard() {
+        // Runs on a thread of its own, which stays at `Policy::Idle` without privileges
+        thread::spawn(|| {
+            set_self_policy(Policy::Other, 0).unwrap();
+            {
+                let _guard = PolicyGuard::elevate(Policy::Batch, 0).unwrap();
+                assert_eq!(Policy::Batch, get_self_policy().unwrap());
+                let inner = PolicyGuard::elevate(Policy::Idle, 0).unwrap();
+                assert_eq!(Policy::Idle, get_self_policy().unwrap());
+                // Leaving `Policy::Idle` needs `CAP_SYS_NICE`
+                match inner.restore() {
+                    Err(ref error) if error.kind() == ErrorKind::PermissionDenied => return,
+                    result => result.unwrap(),
+                }
+                assert_eq!(Policy::Batch, get_self_policy().unwrap());
+            }
+            assert_eq!((Policy::Other, 0), get_self_policy_and_priority().unwrap());
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_policy_guard_invalid() {
+        match PolicyGuard::elevate(Policy::Other, 5) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_with_policy() {
+        set_self_policy(Policy::Other, 0).unwrap();
+        let policy = with_policy(Policy::Batch, 0, || get_self_policy().unwrap()).unwrap();
+        assert_eq!(Policy::Batch, policy);
+        assert_eq!(Policy::Other, get_self_policy().unwrap());
+    }
+
+    #[test]
+    fn test_with_policy_panic() {
+        set_self_policy(Policy::Other, 0).unwrap();
+        let result = panic::catch_unwind(|| {
+            with_policy(Policy::Batch, 0, || panic!("phase failed")).unwrap();
+        });
+        assert!(result.is_err());
+        assert_eq!(Policy::Other, get_self_policy().unwrap());
+    }
+
+    #[test]
+    fn test_affinity_guard() {
+        let online = CpuSet::online().unwrap();
+        set_self_affinity(&online).unwrap();
+        {
+            let outer = AffinityGuard::pin(&CpuSet::single(0)).unwrap();
+            assert_eq!(&online, outer.previous());
+            assert_eq!(CpuSet::single(0), get_self_affinity_auto().unwrap());
+            let _inner = AffinityGuard::pin(&online).unwrap();
+            assert_eq!(online, get_self_affinity_auto().unwrap());
+        }
+        assert_eq!(online, get_self_affinity_auto().unwrap());
+        assert!(AffinityGuard::pin(&CpuSet::new(0)).is_err());
+        assert_eq!(online, get_self_affinity_auto().unwrap());
+    }
+
+    #[test]
+    fn test_with_affinity_panic() {
+        let online = CpuSet::online().unwrap();
+        set_self_affinity(&online).unwrap();
+        let pinned = with_affinity(&CpuSet::single(0), get_self_affinity_auto).unwrap();
+        assert_eq!(CpuSet::single(0), pinned.unwrap());
+        let result = panic::catch_unwind(|| {
+            with_affinity(&CpuSet::single(0), || panic!("reading counters failed")).unwrap();
+        });
+        assert!(result.is_err());
+        assert_eq!(online, get_self_affinity_auto().unwrap());
+    }
+
+    /// Whether the calling thread may lower its nice value back to `nice`, which needs
+    /// `RLIMIT_NICE` or `CAP_SYS_NICE`.
+    fn may_lower_to(nice: i32) -> bool {
+        allowed_nice_range().unwrap().contains(&nice)
+    }
+
+    #[test]
+    fn test_priority_guard() {
+        thread::spawn(|| {
+            let (restorable, nice) = {
+                let guard = PriorityGuard::set(Which::Process, 10).unwrap();
+                assert_eq!(0, guard.previous());
+                assert_eq!(10, get_self_priority(Which::Process).unwrap());
+                let inner = PriorityGuard::set(Which::Process, Nice::MAX).unwrap();
+                assert_eq!(19, get_self_priority(Which::Process).unwrap());
+                let restorable = may_lower_to(10);
+                match inner.restore() {
+                    Ok(()) => assert!(restorable),
+                    Err(error) => {
+                        assert!(!restorable);
+                        assert_eq!(ErrorKind::PermissionDenied, error.kind());
+                    }
+                }
+                let nice = get_self_priority(Which::Process).unwrap();
+                assert_eq!(if restorable { 10 } else { 19 }, nice);
+                (may_lower_to(0), nice)
+            };
+            assert_eq!(if restorable { 0 } else { nice },
+                       get_self_priority(Which::Process).unwrap());
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_with_priority_panic() {
+        thread::spawn(|| {
+            let mut restorable = false;
+            let nice = with_priority(Which::Process, 5, || {
+                restorable = may_lower_to(0);
+                get_self_priority(Which::Process).unwrap()
+            });
+            match nice {
+                Ok(nice) => assert_eq!(5, nice),
+                Err(error) => {
+                    assert!(!restorable);
+                    return assert_eq!(ErrorKind::PermissionDenied, error.kind());
+                }
+            }
+            assert!(restorable);
+            let result = panic::catch_unwind(|| {
+                with_priority(Which::Process, 15, || panic!("compaction failed")).unwrap();
+            });
+            assert!(result.is_err());
+            assert_eq!(0, get_self_priority(Which::Process).unwrap());
+        }).join().unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+//! I/O priorities, how the I/O schedulers order the disk requests of processes
+//!
+//! Only supported on Linux, other systems fail with `ErrorKind::Unsupported`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use libc::{c_int, syscall, SYS_ioprio_get, SYS_ioprio_set};
+use std::io;
+use error::Error;
+use resource::Who;
+
+/// Arguments of `ioprio_set` and `ioprio_get`, selecting the processes like `Who`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const IOPRIO_WHO_PROCESS: c_int = 1;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const IOPRIO_WHO_PGRP: c_int = 2;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const IOPRIO_WHO_USER: c_int = 3;
+
+/// An I/O priority is the class shifted by `IOPRIO_CLASS_SHIFT`, or'ed with the level.
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+const IOPRIO_LEVEL_MASK: u32 = 0x7;
+
+/// The highest level, which is the lowest priority, of `IoClass::Realtime` and
+/// `IoClass::BestEffort`.
+pub const IOPRIO_LEVEL_MAX: u8 = 7;
+
+/// The class of an I/O priority, see `set_io_priority`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IoClass {
+    /// No class was set, the I/O priority follows the nice value as `IoClass::BestEffort` with
+    /// level `(nice + 20) / 5`
+    None,
+    /// Served before any other class, at the risk of starving them. Requires `CAP_SYS_ADMIN`,
+    /// or `CAP_SYS_NICE` since Linux 5.15
+    Realtime,
+    /// The class of most processes
+    BestEffort,
+    /// Only served when no other class needs the disk
+    Idle,
+}
+
+impl IoClass {
+    fn to_raw(self) -> u32 {
+        match self {
+            IoClass::None => 0,
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        }
+    }
+
+    fn from_raw(class: u32) -> Option<IoClass> {
+        match class {
+            0 => Some(IoClass::None),
+            1 => Some(IoClass::Realtime),
+            2 => Some(IoClass::BestEffort),
+            3 => Some(IoClass::Idle),
+            _ => None,
+        }
+    }
+
+    /// Check whether the class has levels, `IoClass::None` and `IoClass::Idle` ignore them.
+    fn has_levels(self) -> bool {
+        match self {
+            IoClass::Realtime | IoClass::BestEffort => true,
+            IoClass::None | IoClass::Idle => false,
+        }
+    }
+}
+
+/// Set the I/O priority of processes to `class` with `level`, where 0 is the highest and
+/// `IOPRIO_LEVEL_MAX` the lowest priority within the class.
+///
+/// Returns an error if `level` is out of range for `IoClass::Realtime` or `IoClass::BestEffort`,
+/// the other classes ignore it. `IoClass::None` makes the I/O priority follow the nice value
+/// again. On Linux, `Who::Process` selects a thread.
+pub fn set_io_priority(who: Who, class: IoClass, level: u8) -> Result<(), Error> {
+    if !class.has_levels() {
+        return set_raw(who, pack(class, 0));
+    }
+    if level > IOPRIO_LEVEL_MAX {
+        let message = format!("I/O priority level {} is not in 0..={}", level, IOPRIO_LEVEL_MAX);
+        return Err(Error::InvalidArgument(message));
+    }
+    set_raw(who, pack(class, level))
+}
+
+/// Get the I/O priority of processes, as its class and level.
+///
+/// For `Who::Group` and `Who::User`, the highest priority of the selected processes is
+/// returned. `IoClass::None` is returned for processes that never set their I/O priority.
+pub fn get_io_priority(who: Who) -> Result<(IoClass, u8), Error> {
+    let ioprio = get_raw(who)?;
+    unpack(ioprio).ok_or_else(|| {
+        let message = format!("unknown I/O priority {:#x}", ioprio);
+        Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+    })
+}
+
+fn pack(class: IoClass, level: u8) -> u32 {
+    class.to_raw() << IOPRIO_CLASS_SHIFT | level as u32
+}
+
+fn unpack(ioprio: u32) -> Option<(IoClass, u8)> {
+    let class = IoClass::from_raw(ioprio >> IOPRIO_CLASS_SHIFT)?;
+    Some((class, (ioprio & IOPRIO_LEVEL_MASK) as u8))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn to_raw_who(who: Who) -> (c_int, c_int) {
+    match who {
+        Who::Process(pid) => (IOPRIO_WHO_PROCESS, pid),
+        Who::Group(pgid) => (IOPRIO_WHO_PGRP, pgid),
+        Who::User(uid) => (IOPRIO_WHO_USER, uid as c_int),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_raw(who: Who, ioprio: u32) -> Result<(), Error> {
+    let (which, who) = to_raw_who(who);
+    match unsafe { syscall(SYS_ioprio_set, which, who, ioprio as c_int) } {
+        0 => Ok(()),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn get_raw(who: Who) -> Result<u32, Error> {
+    let (which, who) = to_raw_who(who);
+    match unsafe { syscall(SYS_ioprio_get, which, who) } {
+        -1 => Err(Error::last_os_error()),
+        ioprio => Ok(ioprio as u32),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_raw(_who: Who, _ioprio: u32) -> Result<(), Error> {
+    Err(unsupported())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn get_raw(_who: Who) -> Result<u32, Error> {
+    Err(unsupported())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn unsupported() -> Error {
+    let message = "I/O priorities are only supported on Linux";
+    Error::Io(io::Error::new(io::ErrorKind::Unsupported, message))
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+    use std::thread;
+    use super::{get_io_priority, pack, set_io_priority, unpack, IoClass};
+    use error::Error;
+    use resource::Who;
+
+    #[test]
+    fn test_pack_io_priority() {
+        assert_eq!(0x4007, pack(IoClass::BestEffort, 7));
+        assert_eq!(0x6000, pack(IoClass::Idle, 0));
+        assert_eq!(Some((IoClass::Realtime, 3)), unpack(0x2003));
+        assert_eq!(Some((IoClass::None, 4)), unpack(4));
+        // Hints above the level are dropped
+        assert_eq!(Some((IoClass::BestEffort, 2)), unpack(0x4002 | 1 << 3));
+        assert_eq!(None, unpack(0x8000));
+    }
+
+    #[test]
+    fn test_set_io_priority() {
+        thread::spawn(|| {
+            set_io_priority(Who::Process(0), IoClass::BestEffort, 7).unwrap();
+            assert_eq!((IoClass::BestEffort, 7), get_io_priority(Who::Process(0)).unwrap());
+            set_io_priority(Who::Process(0), IoClass::Idle, 5).unwrap();
+            assert_eq!((IoClass::Idle, 0), get_io_priority(Who::Process(0)).unwrap());
+            match set_io_priority(Who::Process(0), IoClass::BestEffort, 8) {
+                Err(Error::InvalidArgument(_)) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }).join().unwrap();
+    }
+}
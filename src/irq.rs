@@ -0,0 +1,124 @@
+//! Affinity of interrupts, which CPUs handle the interrupts of a device, set in `/proc/irq`
+//!
+//! Steering the interrupts of e.g. a network card to the CPUs of the threads that process its
+//! packets keeps the data in their caches. Changing the affinity requires root.
+use libc::{EIO, EOVERFLOW};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use cpuset::CpuSet;
+use error::Error;
+
+/// Set the CPUs that handle an interrupt, by writing `/proc/irq/<irq>/smp_affinity`.
+///
+/// Fails with `ErrorKind::PermissionDenied` without root, and with `ErrorKind::Unsupported` if
+/// the interrupt can not be routed to these CPUs, e.g. because the interrupt controller does not
+/// allow it. Returns an error if the interrupt does not exist or `cpuset` has CPUs the system
+/// does not have.
+pub fn set_irq_affinity(irq: u32, cpuset: &CpuSet) -> Result<(), Error> {
+    let path = affinity_file(irq);
+    if !path.exists() {
+        return Err(no_such_irq(irq));
+    }
+    fs::write(path, trimmed_hex_mask(cpuset)).map_err(|error| match error.raw_os_error() {
+        Some(EIO) => {
+            let message = format!("IRQ {} can not be routed to CPUs {}", irq, cpuset);
+            Error::Io(io::Error::new(io::ErrorKind::Unsupported, message))
+        }
+        Some(EOVERFLOW) => {
+            Error::InvalidArgument(format!("CPUs {} are not all present in the system", cpuset))
+        }
+        _ => error.into(),
+    })
+}
+
+/// Get the CPUs that may handle an interrupt, read from `/proc/irq/<irq>/smp_affinity`.
+///
+/// Returns an error if the interrupt does not exist.
+pub fn get_irq_affinity(irq: u32) -> Result<CpuSet, Error> {
+    let path = affinity_file(irq);
+    if !path.exists() {
+        return Err(no_such_irq(irq));
+    }
+    Ok(CpuSet::from_hex_mask(&fs::read_to_string(path)?)?)
+}
+
+/// List the interrupts with an affinity, the numbered directories of `/proc/irq`, in ascending
+/// order.
+pub fn list_irqs() -> Result<Vec<u32>, Error> {
+    let mut irqs = Vec::new();
+    for entry in fs::read_dir("/proc/irq")? {
+        if let Some(irq) = entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+            irqs.push(irq);
+        }
+    }
+    irqs.sort_unstable();
+    Ok(irqs)
+}
+
+fn affinity_file(irq: u32) -> PathBuf {
+    PathBuf::from(format!("/proc/irq/{}/smp_affinity", irq))
+}
+
+fn no_such_irq(irq: u32) -> Error {
+    Error::InvalidArgument(format!("IRQ {} does not exist", irq))
+}
+
+/// Format a mask without the leading chunks of zeros, which the kernel rejects beyond the
+/// number of CPUs it supports.
+fn trimmed_hex_mask(cpuset: &CpuSet) -> String {
+    let mask = cpuset.to_hex_mask_string();
+    let chunks: Vec<&str> = mask.split(',').skip_while(|&chunk| chunk == "00000000").collect();
+    if chunks.is_empty() {
+        "0".to_string()
+    } else {
+        chunks.join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_irq_affinity, list_irqs, set_irq_affinity, trimmed_hex_mask};
+    use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+
+    #[test]
+    fn test_trimmed_hex_mask() {
+        assert_eq!("00000001", trimmed_hex_mask(&CpuSet::single(0)));
+        assert_eq!("00000001,00000000", trimmed_hex_mask(&CpuSet::single(32)));
+        let mut cpuset = CpuSet::new(256);
+        cpuset.set(65);
+        assert_eq!("00000002,00000000,00000000", trimmed_hex_mask(&cpuset));
+        assert_eq!("0", trimmed_hex_mask(&CpuSet::new(64)));
+    }
+
+    #[test]
+    fn test_irq_affinity() {
+        let irqs = list_irqs().unwrap();
+        let mut sorted = irqs.clone();
+        sorted.sort();
+        assert_eq!(sorted, irqs);
+        for &irq in irqs.iter().take(3) {
+            let affinity = get_irq_affinity(irq).unwrap();
+            match set_irq_affinity(irq, &affinity) {
+                Ok(()) => assert_eq!(affinity, get_irq_affinity(irq).unwrap()),
+                Err(error) => {
+                    let kind = error.kind();
+                    assert!(kind == ErrorKind::PermissionDenied || kind == ErrorKind::Unsupported);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_irq() {
+        match get_irq_affinity(u32::MAX) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        match set_irq_affinity(u32::MAX, &CpuSet::single(0)) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -4,13 +4,125 @@
 #![deny(missing_docs)]
 extern crate errno;
 extern crate libc;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+mod error;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod core_sched;
+#[cfg(all(feature = "cgroup", target_os = "linux"))]
+pub mod cgroup;
 mod sched;
 mod resource;
+mod target;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sched_attr;
+mod guard;
+mod ioprio;
+mod load;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod realtime;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod procfs;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod cpu_time;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod timer_slack;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod autogroup;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod oom;
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
 mod cpuset;
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+mod system;
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+mod numa;
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+mod topology;
+#[cfg(target_os = "linux")]
+mod process;
+#[cfg(target_os = "linux")]
+mod thread;
+#[cfg(target_os = "linux")]
+mod config;
+#[cfg(target_os = "linux")]
+mod profile;
+#[cfg(target_os = "linux")]
+mod command;
+#[cfg(target_os = "linux")]
+mod tree;
+#[cfg(target_os = "linux")]
+mod irq;
+#[cfg(target_os = "linux")]
+mod usage;
+#[cfg(target_os = "linux")]
+mod mempolicy;
 
+pub use error::{Error, ErrorKind};
 pub use sched::*;
 pub use resource::*;
+pub use target::{Pid, Target, Tid};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use sched_attr::{get_sched_attr, get_self_sched_attr, get_self_uclamp, get_uclamp,
+                     set_deadline_policy, set_deadline_policy_with_flags,
+                     set_self_deadline_policy, set_self_deadline_policy_with_flags,
+                     set_self_uclamp, set_uclamp, DeadlineParams, SchedAttr, SchedFlags,
+                     UCLAMP_MAX};
+pub use guard::{with_priority, PriorityGuard};
+pub use ioprio::{get_io_priority, set_io_priority, IoClass, IOPRIO_LEVEL_MAX};
+#[cfg(not(target_os = "android"))]
+pub use load::load_average;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use load::{cpu_pressure, Pressure, PsiStats};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use guard::{with_policy, PolicyGuard};
+#[cfg(target_os = "linux")]
+pub use guard::{with_affinity, AffinityGuard};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use realtime::{allowed_nice_range, can_set_realtime, has_cap_sys_nice, is_realtime_kernel,
+                   max_rt_priority, min_allowed_nice, rt_throttling, rtprio_limit,
+                   set_rt_throttling};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use procfs::{context_switches, proc_sched_info, sched_stats, CtxSwitches, ProcSchedInfo,
+                 SchedStats};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use cpu_time::{process_cpu_time, thread_cpu_time};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use timer_slack::{get_timer_slack, get_timer_slack_for, reset_timer_slack,
+                      reset_timer_slack_for, set_timer_slack, set_timer_slack_for};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use autogroup::{get_autogroup, set_autogroup_nice, AutogroupInfo};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use oom::{get_oom_score, get_oom_score_adj, set_oom_score_adj, OOM_SCORE_ADJ_RANGE};
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub use cpuset::{CpuMask, CpuSet, Cpus, ParseCpuSetError, Ranges, ToCpuSet, TryFromCpuSetError};
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub use system::num_cpus_online;
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub use numa::num_nodes;
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
-pub use cpuset::CpuSet;
+pub use topology::cpus_by_package;
+#[cfg(target_os = "linux")]
+pub use process::{context_switches_all_threads, get_affinity_all_threads, get_nice_all_threads,
+                  set_affinity_all_threads, set_nice_all_threads, set_process_affinity,
+                  set_process_policy, AffinityReport, NiceReport, ThreadReport};
+#[cfg(target_os = "linux")]
+pub use thread::{spawn_pinned, ThreadConfig};
+#[cfg(target_os = "linux")]
+pub use config::{ConfigStep, SchedulerConfig};
+#[cfg(target_os = "linux")]
+pub use profile::{apply_profile, Profile, ProfileReport};
+#[cfg(target_os = "linux")]
+pub use command::CommandSchedExt;
+#[cfg(target_os = "linux")]
+pub use tree::{apply_to_tree, TreeReport};
+#[cfg(target_os = "linux")]
+pub use irq::{get_irq_affinity, list_irqs, set_irq_affinity};
+#[cfg(target_os = "linux")]
+pub use usage::{cpu_usage_snapshot, pick_idle_cpu, pick_idle_cpu_with_interval, CpuUsage,
+                PICK_IDLE_INTERVAL};
+#[cfg(target_os = "linux")]
+pub use mempolicy::{get_mempolicy, set_mempolicy, MemPolicy, NodeSet};
@@ -0,0 +1,149 @@
+//! How loaded the system is: the load average, and on Linux the CPU pressure
+#[cfg(not(target_os = "android"))]
+use libc::{c_double, getloadavg};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::fs;
+use std::io;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::time::Duration;
+use error::Error;
+
+/// Get the load average of the system over the last 1, 5 and 15 minutes, the average number of
+/// threads that were running or waiting to run, with `getloadavg`.
+///
+/// On Linux, threads waiting for disk I/O count as well.
+#[cfg(not(target_os = "android"))]
+pub fn load_average() -> Result<(f64, f64, f64), Error> {
+    let mut load: [c_double; 3] = [0.0; 3];
+    match unsafe { getloadavg(load.as_mut_ptr(), 3) } {
+        3 => Ok((load[0], load[1], load[2])),
+        _ => {
+            let message = "the load average is not available";
+            Err(Error::Io(io::Error::other(message)))
+        }
+    }
+}
+
+/// Time threads were stalled waiting for a resource, see `cpu_pressure`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pressure {
+    /// The percentage of the time stalled over the last 10 seconds
+    pub avg10: f64,
+    /// The percentage of the time stalled over the last 60 seconds
+    pub avg60: f64,
+    /// The percentage of the time stalled over the last 300 seconds
+    pub avg300: f64,
+    /// The time stalled since boot
+    pub total: Duration,
+}
+
+/// Pressure stall information of a resource, see `cpu_pressure`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PsiStats {
+    /// The time at least one runnable thread waited
+    pub some: Pressure,
+    /// The time all runnable threads waited, `None` on kernels before 5.13, which do not report
+    /// it for the CPU
+    pub full: Option<Pressure>,
+}
+
+/// Get the CPU pressure of the system, how long runnable threads waited for a CPU, read from
+/// `/proc/pressure/cpu`.
+///
+/// Unlike the load average, this tells how much the threads are slowed down. Fails with
+/// `ErrorKind::Unsupported` if the kernel does not track pressure, without `CONFIG_PSI` or when
+/// booted with `psi=0`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn cpu_pressure() -> Result<PsiStats, Error> {
+    let pressure = fs::read_to_string("/proc/pressure/cpu").map_err(|error| {
+        match error.kind() {
+            io::ErrorKind::NotFound => {
+                let message = "the kernel does not track pressure stall information";
+                Error::Io(io::Error::new(io::ErrorKind::Unsupported, message))
+            }
+            _ => error.into(),
+        }
+    })?;
+    parse_psi(&pressure).ok_or_else(|| {
+        let message = format!("invalid pressure stall information {:?}", pressure);
+        Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+    })
+}
+
+/// Parse a `/proc/pressure/<resource>` file, e.g.
+/// `some avg10=4.76 avg60=3.47 avg300=2.60 total=70694842`, where the total is in microseconds.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn parse_psi(pressure: &str) -> Option<PsiStats> {
+    let mut some = None;
+    let mut full = None;
+    for line in pressure.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next()?;
+        let mut values = [None; 4];
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            let index = ["avg10", "avg60", "avg300", "total"].iter().position(|&k| k == key);
+            if let Some(index) = index {
+                values[index] = Some(value);
+            }
+        }
+        let line_pressure = Pressure {
+            avg10: values[0]?.parse().ok()?,
+            avg60: values[1]?.parse().ok()?,
+            avg300: values[2]?.parse().ok()?,
+            total: Duration::from_micros(values[3]?.parse().ok()?),
+        };
+        match kind {
+            "some" => some = Some(line_pressure),
+            "full" => full = Some(line_pressure),
+            _ => return None,
+        }
+    }
+    Some(PsiStats { some: some?, full })
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+    use std::time::Duration;
+    use super::{cpu_pressure, load_average, parse_psi, Pressure, PsiStats};
+    use error::ErrorKind;
+
+    #[test]
+    fn test_load_average() {
+        let (one, five, fifteen) = load_average().unwrap();
+        assert!(one >= 0.0 && five >= 0.0 && fifteen >= 0.0);
+    }
+
+    #[test]
+    fn test_parse_psi() {
+        let pressure = "some avg10=4.76 avg60=3.47 avg300=2.60 total=70694842\n\
+                        full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n";
+        let some = Pressure {
+            avg10: 4.76,
+            avg60: 3.47,
+            avg300: 2.6,
+            total: Duration::from_micros(70_694_842),
+        };
+        let full = Pressure { avg10: 0.0, avg60: 0.0, avg300: 0.0, total: Duration::from_secs(0) };
+        assert_eq!(Some(PsiStats { some, full: Some(full) }), parse_psi(pressure));
+        // Kernels before 5.13 have no full line for the CPU
+        let pressure = "some avg10=4.76 avg60=3.47 avg300=2.60 total=70694842\n";
+        assert_eq!(Some(PsiStats { some, full: None }), parse_psi(pressure));
+        for &invalid in &["", "some avg10=1.00 avg60=1.00 total=5\n",
+                          "some avg10=x avg60=1.00 avg300=1.00 total=5\n",
+                          "other avg10=1.00 avg60=1.00 avg300=1.00 total=5\n"] {
+            assert_eq!(None, parse_psi(invalid));
+        }
+    }
+
+    #[test]
+    fn test_cpu_pressure() {
+        match cpu_pressure() {
+            Ok(stats) => assert!((0.0..=100.0).contains(&stats.some.avg10)),
+            Err(error) => assert_eq!(ErrorKind::Unsupported, error.kind()),
+        }
+    }
+}
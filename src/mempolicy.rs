@@ -0,0 +1,139 @@
+//! NUMA memory policy, the nodes the memory of a thread is allocated from
+//!
+//! Pinning a thread to the CPUs of a node with `CpuSet::for_node` pays off when its memory comes
+//! from the same node. The policy applies to the calling thread and is inherited by the threads
+//! and processes it starts.
+use libc::{c_int, c_ulong, syscall, SYS_get_mempolicy, SYS_set_mempolicy, EINVAL, ENOSYS};
+use std::io;
+use std::ptr;
+use cpuset::CpuSet;
+use error::Error;
+
+/// A set of NUMA nodes, where bit `n` is node `n`, see `set_mempolicy`.
+pub type NodeSet = CpuSet;
+
+/// Number of nodes `get_mempolicy` has room for, `MAX_NUMNODES` of the largest kernels.
+const MAX_NODES: usize = 1024;
+
+/// Flags the kernel or'es into the mode returned by `get_mempolicy`.
+const MPOL_MODE_FLAGS: c_int = 0x7 << 13;
+
+/// How memory is allocated from the nodes of a `NodeSet`, see `set_mempolicy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MemPolicy {
+    /// The policy of the process, by default the node of the CPU the thread runs on. Takes no
+    /// nodes.
+    Default,
+    /// From the first of the nodes, falling back to others when it runs out of memory
+    Preferred,
+    /// Only from the nodes, the allocation fails when they run out of memory
+    Bind,
+    /// Page by page from each of the nodes in turn
+    Interleave,
+    /// From the node of the CPU the thread runs on, falling back to others. Takes no nodes.
+    Local,
+}
+
+impl MemPolicy {
+    fn to_c_int(self) -> c_int {
+        match self {
+            MemPolicy::Default => 0,
+            MemPolicy::Preferred => 1,
+            MemPolicy::Bind => 2,
+            MemPolicy::Interleave => 3,
+            MemPolicy::Local => 4,
+        }
+    }
+
+    fn from_c_int(mode: c_int) -> Result<MemPolicy, Error> {
+        match mode & !MPOL_MODE_FLAGS {
+            0 => Ok(MemPolicy::Default),
+            1 => Ok(MemPolicy::Preferred),
+            2 => Ok(MemPolicy::Bind),
+            3 => Ok(MemPolicy::Interleave),
+            4 => Ok(MemPolicy::Local),
+            mode => {
+                let message = format!("unknown memory policy {}", mode);
+                Err(Error::Io(io::Error::new(io::ErrorKind::InvalidData, message)))
+            }
+        }
+    }
+}
+
+/// Set the NUMA memory policy of the calling thread, for the memory it allocates from now on.
+///
+/// `MemPolicy::Default` and `MemPolicy::Local` ignore `nodes`. The nodes are restricted to the
+/// memory nodes of the cgroup cpuset of the thread, `cpuset.mems`: nodes outside of it are
+/// ignored, and returns an error if none of the nodes is allowed or online. Fails with
+/// `ErrorKind::Unsupported` if the kernel was built without NUMA support.
+pub fn set_mempolicy(policy: MemPolicy, nodes: &NodeSet) -> Result<(), Error> {
+    let (mask, max_node) = match policy {
+        MemPolicy::Default | MemPolicy::Local => (ptr::null(), 0),
+        // The kernel reads one bit less than `max_node`
+        _ => (nodes.as_slice().as_ptr(), nodes.capacity() as c_ulong + 1),
+    };
+    match unsafe { syscall(SYS_set_mempolicy, policy.to_c_int(), mask, max_node) } {
+        0 => Ok(()),
+        _ => Err(mempolicy_error(Error::last_os_error(), || {
+            format!("memory policy {:?} with nodes {} is not allowed", policy, nodes)
+        })),
+    }
+}
+
+/// Get the NUMA memory policy of the calling thread and its nodes, see `set_mempolicy`.
+///
+/// The nodes are empty for `MemPolicy::Default` and `MemPolicy::Local`.
+pub fn get_mempolicy() -> Result<(MemPolicy, NodeSet), Error> {
+    let mut mode: c_int = 0;
+    let mut nodes = NodeSet::new(MAX_NODES);
+    let mask = nodes.as_mut_slice().as_mut_ptr();
+    let flags: c_ulong = 0;
+    match unsafe {
+        syscall(SYS_get_mempolicy, &mut mode, mask, MAX_NODES as c_ulong, ptr::null::<u8>(), flags)
+    } {
+        0 => Ok((MemPolicy::from_c_int(mode)?, nodes)),
+        _ => Err(mempolicy_error(Error::last_os_error(), || "invalid arguments".to_string())),
+    }
+}
+
+/// Tell apart a kernel without NUMA and arguments it rejected.
+fn mempolicy_error<F: FnOnce() -> String>(error: Error, message: F) -> Error {
+    match error.raw_os_error() {
+        Some(EINVAL) => Error::InvalidArgument(message()),
+        Some(ENOSYS) => {
+            let message = "the kernel was built without NUMA support";
+            Error::Io(io::Error::new(io::ErrorKind::Unsupported, message))
+        }
+        _ => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use super::{get_mempolicy, set_mempolicy, MemPolicy, NodeSet};
+    use error::{Error, ErrorKind};
+
+    #[test]
+    fn test_set_mempolicy() {
+        thread::spawn(|| {
+            match set_mempolicy(MemPolicy::Preferred, &NodeSet::single(0)) {
+                Ok(()) => {}
+                Err(error) => return assert_eq!(ErrorKind::Unsupported, error.kind()),
+            }
+            assert_eq!((MemPolicy::Preferred, NodeSet::single(0)), get_mempolicy().unwrap());
+            set_mempolicy(MemPolicy::Default, &NodeSet::new(0)).unwrap();
+            assert_eq!((MemPolicy::Default, NodeSet::new(0)), get_mempolicy().unwrap());
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_set_mempolicy_invalid() {
+        match set_mempolicy(MemPolicy::Bind, &NodeSet::new(64)) {
+            Err(Error::InvalidArgument(_)) => {}
+            Err(ref error) if error.kind() == ErrorKind::Unsupported => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! NUMA nodes and the CPUs belonging to them, as reported by the kernel in sysfs
+use std::fs;
+use std::io;
+use std::path::Path;
+use cpuset::CpuSet;
+use error::Error;
+use system::read_cpulist;
+
+/// Directory where the kernel describes the NUMA nodes of the system.
+const SYSFS_NODE: &str = "/sys/devices/system/node";
+
+impl CpuSet {
+    /// Get the CPUs belonging to NUMA node `node`.
+    /// Read from `/sys/devices/system/node/node<node>/cpulist`.
+    pub fn for_node(node: usize) -> Result<CpuSet, Error> {
+        let path = Path::new(SYSFS_NODE).join(format!("node{}", node)).join("cpulist");
+        match read_cpulist(path) {
+            Err(Error::Io(ref error)) if error.kind() == io::ErrorKind::NotFound => {
+                Err(Error::NoSuchNode(node))
+            }
+            result => result,
+        }
+    }
+}
+
+/// Get the number of NUMA nodes in the system.
+///
+/// Counts the `node<N>` entries in `/sys/devices/system/node`.
+pub fn num_nodes() -> Result<usize, Error> {
+    let mut nodes = 0;
+    for entry in fs::read_dir(SYSFS_NODE)? {
+        let name = entry?.file_name();
+        if name.to_str().is_some_and(is_node_name) {
+            nodes += 1;
+        }
+    }
+    Ok(nodes)
+}
+
+/// Check if `name` is a node directory name, e.g. `node0`.
+fn is_node_name(name: &str) -> bool {
+    name.starts_with("node") && name.len() > 4 && name[4..].bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_node_name, num_nodes};
+    use cpuset::CpuSet;
+    use error::Error;
+
+    #[test]
+    fn test_is_node_name() {
+        assert!(is_node_name("node0"));
+        assert!(is_node_name("node12"));
+        assert!(!is_node_name("node"));
+        assert!(!is_node_name("nodes"));
+        assert!(!is_node_name("has_cpu"));
+    }
+
+    #[test]
+    fn test_for_node() {
+        assert!(num_nodes().unwrap() >= 1);
+        assert!(CpuSet::for_node(0).unwrap().is_subset(&CpuSet::possible().unwrap()));
+    }
+
+    #[test]
+    fn test_for_missing_node() {
+        match CpuSet::for_node(1 << 20) {
+            Err(Error::NoSuchNode(node)) => assert_eq!(1 << 20, node),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
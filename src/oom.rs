@@ -0,0 +1,108 @@
+//! How readily the kernel kills a process when the system runs out of memory
+//!
+//! The out-of-memory killer picks the process with the highest score, computed from its memory
+//! use and shifted by its `oom_score_adj`.
+use libc::ESRCH;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use error::Error;
+use procfs::proc_dir;
+use target::Pid;
+
+/// The range of `oom_score_adj`, where -1000 exempts a process from the out-of-memory killer
+/// and 1000 makes it the first victim.
+pub const OOM_SCORE_ADJ_RANGE: RangeInclusive<i32> = -1000..=1000;
+
+/// Set the `oom_score_adj` of a process, in the range `-1000..=1000`, by writing
+/// `/proc/<pid>/oom_score_adj`.
+///
+/// Lowering it below the lowest value set since the process started requires
+/// `CAP_SYS_RESOURCE`, otherwise this fails with `ErrorKind::PermissionDenied`. Returns an error
+/// if `adj` is out of range.
+pub fn set_oom_score_adj(pid: Pid, adj: i32) -> Result<(), Error> {
+    if !OOM_SCORE_ADJ_RANGE.contains(&adj) {
+        return Err(Error::InvalidArgument(format!("oom_score_adj {} is not in {:?}",
+                                                  adj, OOM_SCORE_ADJ_RANGE)));
+    }
+    fs::write(oom_file(pid, "oom_score_adj"), adj.to_string())
+        .map_err(|error| missing_process(pid, error))
+}
+
+/// Get the `oom_score_adj` of a process, read from `/proc/<pid>/oom_score_adj`.
+pub fn get_oom_score_adj(pid: Pid) -> Result<i32, Error> {
+    read_score(pid, "oom_score_adj")
+}
+
+/// Get the score the out-of-memory killer computed for a process, including its
+/// `oom_score_adj`, read from `/proc/<pid>/oom_score`.
+pub fn get_oom_score(pid: Pid) -> Result<i32, Error> {
+    read_score(pid, "oom_score")
+}
+
+fn oom_file(pid: Pid, name: &str) -> PathBuf {
+    proc_dir(pid.into()).join(name)
+}
+
+fn read_score(pid: Pid, name: &str) -> Result<i32, Error> {
+    let score = fs::read_to_string(oom_file(pid, name)).map_err(|error| {
+        missing_process(pid, error)
+    })?;
+    score.trim().parse().map_err(|_| {
+        let message = format!("invalid {} {:?}", name, score);
+        Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+    })
+}
+
+/// Report a missing file of a process as a missing process.
+fn missing_process(pid: Pid, error: io::Error) -> Error {
+    match error.kind() {
+        io::ErrorKind::NotFound if !proc_dir(pid.into()).exists() => {
+            Error::from_raw_os_error(ESRCH)
+        }
+        _ => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use super::{get_oom_score, get_oom_score_adj, set_oom_score_adj};
+    use error::{Error, ErrorKind};
+    use target::Pid;
+
+    #[test]
+    fn test_set_oom_score_adj() {
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let pid = Pid::from(&child);
+        let before = get_oom_score_adj(pid);
+        let result = set_oom_score_adj(pid, 1000);
+        let after = get_oom_score_adj(pid);
+        let score = get_oom_score(pid);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(get_oom_score_adj(Pid::this()).unwrap(), before.unwrap());
+        result.unwrap();
+        assert_eq!(1000, after.unwrap());
+        assert!(score.unwrap() >= 1000);
+    }
+
+    #[test]
+    fn test_set_oom_score_adj_invalid() {
+        for &adj in &[-1001, 1001] {
+            match set_oom_score_adj(Pid::this(), adj) {
+                Err(Error::InvalidArgument(_)) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_oom_score_missing_process() {
+        let error = get_oom_score(Pid::from_raw(i32::MAX)).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+        let error = set_oom_score_adj(Pid::from_raw(i32::MAX), 0).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+}
@@ -0,0 +1,328 @@
+//! Scheduling of every thread of a process, enumerated from `/proc/<pid>/task`
+use libc::{ENOENT, ESRCH};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use cpuset::CpuSet;
+use error::{Error, ErrorKind};
+use procfs::{context_switches, CtxSwitches};
+use resource::{get_priority_for, set_priority_for, Who};
+use sched::{get_affinity_auto, set_affinity, set_policy, validate_priority, Policy};
+use target::{Pid, Tid};
+
+/// Set the scheduling policy for every thread of a process.
+///
+/// The policy is applied to each thread listed in `/proc/<pid>/task`, and the threads where it
+/// failed are returned with their error. Threads that exit in the meantime fail with
+/// `ErrorKind::NoSuchProcess`, threads started in the meantime can be missed and keep the policy
+/// of the thread that started them. Returns an error without changing any thread if the priority is
+/// invalid for the policy, see `set_policy`, or if the threads can not be listed.
+pub fn set_process_policy(pid: Pid, policy: Policy, priority: i32)
+                          -> Result<Vec<(Tid, Error)>, Error> {
+    validate_priority(policy, priority)?;
+    for_each_thread(pid, |tid| set_policy(tid, policy, priority))
+}
+
+/// Set the cpu affinity for every thread of a process, like `taskset --all-tasks`.
+///
+/// The threads where it failed are returned with their error, see `set_process_policy`.
+pub fn set_process_affinity(pid: Pid, cpuset: &CpuSet) -> Result<Vec<(Tid, Error)>, Error> {
+    for_each_thread(pid, |tid| set_affinity(tid, cpuset))
+}
+
+/// Number of times `set_affinity_all_threads` lists the threads before giving up on new ones.
+const MAX_PASSES: usize = 4;
+
+/// Outcome of `set_affinity_all_threads`.
+pub type AffinityReport = ThreadReport;
+
+/// Outcome of `set_nice_all_threads`.
+pub type NiceReport = ThreadReport;
+
+/// Outcome per thread of applying a setting to every thread of a process.
+#[derive(Debug, Default)]
+pub struct ThreadReport {
+    /// Threads the setting was applied to
+    pub applied: Vec<Tid>,
+    /// Threads where applying the setting failed, with the error
+    pub failed: Vec<(Tid, Error)>,
+    /// Threads that were listed but exited before the setting was applied
+    pub vanished: Vec<Tid>,
+    /// Threads that were not listed at first but started while the setting was applied.
+    /// These are also in one of the other lists.
+    pub appeared: Vec<Tid>,
+}
+
+/// Set the cpu affinity for every thread of a process, like `taskset --all-tasks`, reporting
+/// the outcome per thread.
+///
+/// Threads can start and exit while the affinity is applied. After applying it to the threads
+/// in `/proc/<pid>/task` the threads are listed again, and the affinity is applied to new ones,
+/// until no new threads are found or the threads were listed 4 times. A thread started after
+/// the last listing can still be missed. Returns an error if the threads can not be listed the
+/// first time.
+pub fn set_affinity_all_threads(pid: Pid, cpuset: &CpuSet) -> Result<AffinityReport, Error> {
+    apply_all_threads(|| threads_of(pid), |tid| set_affinity(tid, cpuset))
+}
+
+/// Get the cpu affinity for every thread of a process, sized like in `get_affinity_auto`.
+/// Threads that exit while the affinity is read are left out.
+pub fn get_affinity_all_threads(pid: Pid) -> Result<Vec<(Tid, CpuSet)>, Error> {
+    let mut affinities = Vec::new();
+    for tid in threads_of(pid)? {
+        match get_affinity_auto(tid) {
+            Ok(cpuset) => affinities.push((tid, cpuset)),
+            Err(ref error) if error.kind() == ErrorKind::NoSuchProcess => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(affinities)
+}
+
+/// Set the nice value for every thread of a process, reporting the outcome per thread.
+///
+/// On Linux the nice value belongs to a thread, so `set_priority` with `Which::Process` only
+/// changes the main thread of a process. New threads are handled like in
+/// `set_affinity_all_threads`.
+pub fn set_nice_all_threads(pid: Pid, nice: i32) -> Result<NiceReport, Error> {
+    apply_all_threads(|| threads_of(pid), |tid| set_priority_for(Who::Process(tid.as_raw()), nice))
+}
+
+/// Get the nice value for every thread of a process.
+/// Threads that exit while the nice values are read are left out.
+pub fn get_nice_all_threads(pid: Pid) -> Result<Vec<(Tid, i32)>, Error> {
+    let mut nice_values = Vec::new();
+    for tid in threads_of(pid)? {
+        match get_priority_for(Who::Process(tid.as_raw())) {
+            Ok(nice) => nice_values.push((tid, nice)),
+            Err(ref error) if error.kind() == ErrorKind::NoSuchProcess => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(nice_values)
+}
+
+/// Get the context switch counters for every thread of a process, see `context_switches`.
+/// Threads that exit while the counters are read are left out.
+pub fn context_switches_all_threads(pid: Pid) -> Result<HashMap<Tid, CtxSwitches>, Error> {
+    let mut switches = HashMap::new();
+    for tid in threads_of(pid)? {
+        match context_switches(tid) {
+            Ok(counters) => {
+                switches.insert(tid, counters);
+            }
+            Err(ref error) if error.raw_os_error() == Some(ENOENT) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(switches)
+}
+
+/// Call `apply` for every thread returned by `list`, listing again until no new threads appear.
+pub(crate) fn apply_all_threads<L, F>(mut list: L, mut apply: F) -> Result<ThreadReport, Error>
+    where L: FnMut() -> Result<Vec<Tid>, Error>,
+          F: FnMut(Tid) -> Result<(), Error>
+{
+    let mut report = ThreadReport::default();
+    let mut seen = Vec::new();
+    for pass in 0..MAX_PASSES {
+        let threads = match list() {
+            Ok(threads) => threads,
+            Err(error) if pass == 0 => return Err(error),
+            Err(_) => break,
+        };
+        let new: Vec<Tid> = threads.into_iter().filter(|tid| !seen.contains(tid)).collect();
+        if new.is_empty() {
+            break;
+        }
+        for &tid in &new {
+            match apply(tid) {
+                Ok(()) => report.applied.push(tid),
+                Err(ref error) if error.kind() == ErrorKind::NoSuchProcess => {
+                    report.vanished.push(tid)
+                }
+                Err(error) => report.failed.push((tid, error)),
+            }
+            if pass > 0 {
+                report.appeared.push(tid);
+            }
+        }
+        seen.extend(new);
+    }
+    Ok(report)
+}
+
+/// Call `f` for every thread of a process, collecting the failures.
+fn for_each_thread<F>(pid: Pid, mut f: F) -> Result<Vec<(Tid, Error)>, Error>
+    where F: FnMut(Tid) -> Result<(), Error>
+{
+    let mut failures = Vec::new();
+    for tid in threads_of(pid)? {
+        if let Err(error) = f(tid) {
+            failures.push((tid, error));
+        }
+    }
+    Ok(failures)
+}
+
+/// List the threads of a process, read from `/proc/<pid>/task`. Fails with `ESRCH` if the
+/// process does not exist.
+pub(crate) fn threads_of(pid: Pid) -> Result<Vec<Tid>, Error> {
+    let mut threads = Vec::new();
+    let entries = fs::read_dir(task_dir(pid)).map_err(|error| match error.raw_os_error() {
+        Some(ENOENT) => Error::from_raw_os_error(ESRCH),
+        _ => error.into(),
+    })?;
+    for entry in entries {
+        let name = entry?.file_name();
+        if let Some(tid) = name.to_str().and_then(|name| name.parse().ok()) {
+            threads.push(Tid::from_raw(tid));
+        }
+    }
+    threads.sort();
+    Ok(threads)
+}
+
+/// Directory listing the threads of a process.
+fn task_dir(pid: Pid) -> PathBuf {
+    PathBuf::from(format!("/proc/{}/task", pid.as_raw()))
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::{EPERM, ESRCH};
+    use std::process::Command;
+    use std::sync::mpsc;
+    use std::thread;
+    use super::{apply_all_threads, context_switches_all_threads, get_affinity_all_threads,
+                get_nice_all_threads, set_affinity_all_threads, set_nice_all_threads,
+                set_process_affinity, set_process_policy, threads_of};
+    use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+    use resource::{set_priority, Which};
+    use sched::{get_affinity, get_policy, get_self_affinity_auto, Policy};
+    use target::{Pid, Tid};
+
+    #[test]
+    fn test_threads_of() {
+        let (sender, receiver) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            let tid = Tid::current();
+            receiver.recv().unwrap_err();
+            tid
+        });
+        let threads = threads_of(Pid::this()).unwrap();
+        assert!(threads.contains(&Tid::current()));
+        drop(sender);
+        assert!(threads.contains(&handle.join().unwrap()));
+        let error = threads_of(Pid::from_raw(i32::MAX)).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+
+    #[test]
+    fn test_set_process_policy() {
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let failures = set_process_policy(Pid::from(&child), Policy::Batch, 0);
+        let affinity_failures = set_process_affinity(Pid::from(&child), &CpuSet::single(0));
+        let policy = get_policy(&child);
+        let affinity = get_affinity(&child, 1);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(failures.unwrap().is_empty());
+        assert!(affinity_failures.unwrap().is_empty());
+        assert_eq!(Policy::Batch, policy.unwrap());
+        assert_eq!(CpuSet::single(0), affinity.unwrap());
+    }
+
+    #[test]
+    fn test_set_process_policy_invalid() {
+        match set_process_policy(Pid::this(), Policy::Other, 10) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_apply_all_threads() {
+        let tids = |raw: &[i32]| raw.iter().map(|&tid| Tid::from_raw(tid)).collect::<Vec<_>>();
+        let mut listings = vec![tids(&[1]), tids(&[1, 3, 4, 5]), tids(&[1, 3, 4]), tids(&[1, 2, 3])];
+        let report = apply_all_threads(|| Ok(listings.pop().unwrap()), |tid| {
+            match tid.as_raw() {
+                2 => Err(Error::from_raw_os_error(ESRCH)),
+                5 => Err(Error::from_raw_os_error(EPERM)),
+                _ => Ok(()),
+            }
+        }).unwrap();
+        assert_eq!(tids(&[1, 3, 4]), report.applied);
+        assert_eq!(tids(&[2]), report.vanished);
+        assert_eq!(vec![5], report.failed.iter().map(|&(tid, _)| tid.as_raw()).collect::<Vec<_>>());
+        assert_eq!(tids(&[4, 5]), report.appeared);
+        assert!(listings.is_empty());
+    }
+
+    #[test]
+    fn test_apply_all_threads_list_error() {
+        let result = apply_all_threads(|| Err(Error::from_raw_os_error(ESRCH)), |_| Ok(()));
+        assert_eq!(ErrorKind::NoSuchProcess, result.unwrap_err().kind());
+    }
+
+    #[test]
+    fn test_set_affinity_all_threads() {
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let report = set_affinity_all_threads(Pid::from(&child), &CpuSet::single(0));
+        let affinities = get_affinity_all_threads(Pid::from(&child));
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let report = report.unwrap();
+        assert_eq!(vec![Pid::from(&child).as_raw()],
+                   report.applied.iter().map(|tid| tid.as_raw()).collect::<Vec<_>>());
+        assert!(report.failed.is_empty() && report.vanished.is_empty());
+        let affinities = affinities.unwrap();
+        assert_eq!(1, affinities.len());
+        assert_eq!(CpuSet::single(0), affinities[0].1);
+    }
+
+    #[test]
+    fn test_get_affinity_all_threads_self() {
+        let affinities = get_affinity_all_threads(Pid::this()).unwrap();
+        let current = affinities.iter().find(|&&(tid, _)| tid == Tid::current()).unwrap();
+        assert_eq!(get_self_affinity_auto().unwrap(), current.1);
+    }
+
+    #[test]
+    fn test_set_nice_all_threads() {
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let report = set_nice_all_threads(Pid::from(&child), 7);
+        let nice_values = get_nice_all_threads(Pid::from(&child));
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let report = report.unwrap();
+        assert_eq!(1, report.applied.len());
+        assert!(report.failed.is_empty() && report.vanished.is_empty());
+        assert_eq!(vec![(Tid::from_raw(Pid::from(&child).as_raw()), 7)], nice_values.unwrap());
+    }
+
+    #[test]
+    fn test_get_nice_all_threads_self() {
+        let (sender, receiver) = mpsc::channel::<()>();
+        let (tid_sender, tid_receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            set_priority(Which::Process, 0, 4).unwrap();
+            tid_sender.send(Tid::current()).unwrap();
+            receiver.recv().unwrap_err();
+        });
+        let tid = tid_receiver.recv().unwrap();
+        let nice_values = get_nice_all_threads(Pid::this()).unwrap();
+        drop(sender);
+        handle.join().unwrap();
+        assert!(nice_values.contains(&(tid, 4)));
+    }
+
+    #[test]
+    fn test_context_switches_all_threads() {
+        let switches = context_switches_all_threads(Pid::this()).unwrap();
+        assert!(switches[&Tid::current()].voluntary.is_some());
+        let error = context_switches_all_threads(Pid::from_raw(i32::MAX)).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+}
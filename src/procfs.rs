@@ -0,0 +1,390 @@
+//! Scheduling information of threads, read from `/proc`
+use libc::{sysconf, _SC_CLK_TCK};
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use error::Error;
+use sched::Policy;
+use target::Target;
+
+/// Scheduling fields of `/proc/<pid>/stat`, see `proc_sched_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcSchedInfo {
+    /// The name of the command, which can contain any character
+    pub comm: String,
+    /// The state, e.g. `R` for running or `S` for sleeping
+    pub state: char,
+    /// The nice value
+    pub nice: i32,
+    /// The real-time priority, 0 for the normal policies
+    pub rt_priority: u32,
+    /// The scheduling policy
+    pub policy: Policy,
+    /// The CPU it last ran on
+    pub processor: usize,
+    /// The time spent in user mode
+    pub utime: Duration,
+    /// The time spent in kernel mode
+    pub stime: Duration,
+}
+
+/// Get the scheduling fields of `/proc/<pid>/stat` for a thread.
+///
+/// The times are converted from clock ticks. The calling thread is read from
+/// `/proc/thread-self/stat`. For a process, the times are summed over all of its threads.
+pub fn proc_sched_info<T: Into<Target>>(target: T) -> Result<ProcSchedInfo, Error> {
+    let stat = fs::read_to_string(proc_dir(target.into()).join("stat"))?;
+    parse_stat(&stat, clock_ticks())
+}
+
+/// Scheduler statistics of a thread, see `sched_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedStats {
+    /// The time spent running on a CPU
+    pub run_time: Duration,
+    /// The time spent waiting on a run queue to run
+    pub wait_time: Duration,
+    /// The number of times it ran on a CPU
+    pub timeslices: u64,
+    /// The number of times it gave up the CPU, e.g. to wait for I/O
+    pub voluntary_switches: Option<u64>,
+    /// The number of times it was preempted
+    pub involuntary_switches: Option<u64>,
+    /// The number of times it moved to another CPU
+    pub migrations: Option<u64>,
+}
+
+impl SchedStats {
+    /// Get the statistics since an earlier sample of the same thread.
+    ///
+    /// Counters that are missing in either sample are `None`. Counters that went backwards,
+    /// e.g. because the samples are of different threads, are 0.
+    pub fn delta(&self, earlier: &SchedStats) -> SchedStats {
+        let delta = |now: Option<u64>, earlier: Option<u64>| {
+            now.and_then(|now| earlier.map(|earlier| now.saturating_sub(earlier)))
+        };
+        SchedStats {
+            run_time: self.run_time.saturating_sub(earlier.run_time),
+            wait_time: self.wait_time.saturating_sub(earlier.wait_time),
+            timeslices: self.timeslices.saturating_sub(earlier.timeslices),
+            voluntary_switches: delta(self.voluntary_switches, earlier.voluntary_switches),
+            involuntary_switches: delta(self.involuntary_switches, earlier.involuntary_switches),
+            migrations: delta(self.migrations, earlier.migrations),
+        }
+    }
+}
+
+/// Get the scheduler statistics of a thread from `/proc/<pid>/schedstat` and
+/// `/proc/<pid>/sched`.
+///
+/// The times and time slices are read from `schedstat`, which needs a kernel built with
+/// `CONFIG_SCHED_INFO`. The counters are read from `sched`, which is only available with
+/// `CONFIG_SCHED_DEBUG`, they are `None` without it or if the kernel does not report them.
+pub fn sched_stats<T: Into<Target>>(target: T) -> Result<SchedStats, Error> {
+    let dir = proc_dir(target.into());
+    let mut stats = parse_schedstat(&fs::read_to_string(dir.join("schedstat"))?)?;
+    match fs::read_to_string(dir.join("sched")) {
+        Ok(sched) => parse_sched(&sched, &mut stats),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+    Ok(stats)
+}
+
+/// Parse a `/proc/<pid>/schedstat` file, the run time and wait time in nanoseconds and the
+/// number of time slices.
+fn parse_schedstat(schedstat: &str) -> Result<SchedStats, Error> {
+    let fields: Vec<&str> = schedstat.split_whitespace().collect();
+    if fields.len() < 3 {
+        return Err(invalid_stat("missing field"));
+    }
+    Ok(SchedStats {
+        run_time: Duration::from_nanos(parse_field(fields[0])?),
+        wait_time: Duration::from_nanos(parse_field(fields[1])?),
+        timeslices: parse_field(fields[2])?,
+        ..SchedStats::default()
+    })
+}
+
+/// Parse the counters of a `/proc/<pid>/sched` file, made of `name : value` lines.
+fn parse_sched(sched: &str, stats: &mut SchedStats) {
+    for line in sched.lines() {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim().parse().ok()),
+            None => continue,
+        };
+        match name {
+            "nr_voluntary_switches" => stats.voluntary_switches = value,
+            "nr_involuntary_switches" => stats.involuntary_switches = value,
+            "se.nr_migrations" => stats.migrations = value,
+            _ => {}
+        }
+    }
+}
+
+/// Context switch counters of a thread, see `context_switches`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CtxSwitches {
+    /// The number of times it gave up the CPU, e.g. to wait for I/O
+    pub voluntary: Option<u64>,
+    /// The number of times it was preempted
+    pub involuntary: Option<u64>,
+}
+
+impl CtxSwitches {
+    /// Get the counters since an earlier sample of the same thread, see `SchedStats::delta`.
+    pub fn delta(&self, earlier: &CtxSwitches) -> CtxSwitches {
+        let delta = |now: Option<u64>, earlier: Option<u64>| {
+            now.and_then(|now| earlier.map(|earlier| now.saturating_sub(earlier)))
+        };
+        CtxSwitches {
+            voluntary: delta(self.voluntary, earlier.voluntary),
+            involuntary: delta(self.involuntary, earlier.involuntary),
+        }
+    }
+}
+
+/// Get the context switch counters of a thread, read from the `voluntary_ctxt_switches` and
+/// `nonvoluntary_ctxt_switches` lines of `/proc/<pid>/status`.
+///
+/// A counter is `None` if the kernel does not report it, before Linux 2.6.23. For a process,
+/// these are the counters of its main thread, see `context_switches_all_threads`. Many
+/// involuntary switches tell that a thread is preempted by others with a higher priority or on
+/// the same CPU.
+pub fn context_switches<T: Into<Target>>(target: T) -> Result<CtxSwitches, Error> {
+    let status = fs::read_to_string(proc_dir(target.into()).join("status"))?;
+    Ok(parse_ctxt_switches(&status))
+}
+
+/// Parse the context switch counters of a `/proc/<pid>/status` file.
+fn parse_ctxt_switches(status: &str) -> CtxSwitches {
+    let mut switches = CtxSwitches::default();
+    for line in status.lines() {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name, value.trim().parse().ok()),
+            None => continue,
+        };
+        match name {
+            "voluntary_ctxt_switches" => switches.voluntary = value,
+            "nonvoluntary_ctxt_switches" => switches.involuntary = value,
+            _ => {}
+        }
+    }
+    switches
+}
+
+/// Directory describing a thread in `/proc`.
+pub(crate) fn proc_dir(target: Target) -> PathBuf {
+    match target {
+        Target::CurrentThread => PathBuf::from("/proc/thread-self"),
+        target => PathBuf::from(format!("/proc/{}", target.as_raw())),
+    }
+}
+
+/// Get the number of clock ticks per second, the unit of the times in `/proc`.
+fn clock_ticks() -> u64 {
+    match unsafe { sysconf(_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+/// Parse a `/proc/<pid>/stat` file.
+///
+/// The command name is enclosed in parentheses but can contain spaces and parentheses itself,
+/// so it ends at the last `)`.
+fn parse_stat(stat: &str, ticks: u64) -> Result<ProcSchedInfo, Error> {
+    let (start, end) = match (stat.find('('), stat.rfind(')')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return Err(invalid_stat("no command name")),
+    };
+    // Field 3, the state, is the first after the command name
+    let fields: Vec<&str> = stat[end + 1..].split_whitespace().collect();
+    let field = |number: usize| {
+        fields.get(number - 3).cloned().ok_or_else(|| invalid_stat("missing field"))
+    };
+    let ticks_to_duration = |number: usize| -> Result<Duration, Error> {
+        let value: u64 = parse_field(field(number)?)?;
+        Ok(Duration::from_secs(value / ticks) +
+           Duration::from_nanos((value % ticks) * 1_000_000_000 / ticks))
+    };
+    Ok(ProcSchedInfo {
+        comm: stat[start + 1..end].to_string(),
+        state: field(3)?.chars().next().unwrap_or('?'),
+        utime: ticks_to_duration(14)?,
+        stime: ticks_to_duration(15)?,
+        nice: parse_field(field(19)?)?,
+        processor: parse_field(field(39)?)?,
+        rt_priority: parse_field(field(40)?)?,
+        policy: Policy::try_from(parse_field::<i32>(field(41)?)?)?,
+    })
+}
+
+fn parse_field<F: FromStr>(field: &str) -> Result<F, Error> {
+    field.parse().map_err(|_| invalid_stat("invalid number"))
+}
+
+fn invalid_stat(message: &str) -> Error {
+    let message = format!("invalid /proc stat: {}", message);
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{context_switches, parse_ctxt_switches, parse_sched, parse_schedstat, parse_stat,
+                proc_sched_info, sched_stats, CtxSwitches, SchedStats};
+    use error::{Error, ErrorKind};
+    use sched::{set_self_policy, Policy};
+    use target::{Pid, Target, Tid};
+
+    fn stat(comm: &str) -> String {
+        format!("6158 ({}) S 6152 6158 6152 0 -1 4194304 83 0 0 0 250 101 0 0 39 19 1 0 173935 \
+                 2703360 329 18446744073709551615 94306633015296 94306633035177 140731407013024 \
+                 0 0 0 0 0 0 0 0 0 17 3 5 3 0 0 0 94306633051184 94306633052800 94307390566400 \
+                 140731407021481 140731407021501 140731407021501 140731407024107 0\n", comm)
+    }
+
+    #[test]
+    fn test_parse_stat() {
+        let info = parse_stat(&stat("cat"), 100).unwrap();
+        assert_eq!("cat", info.comm);
+        assert_eq!('S', info.state);
+        assert_eq!(Duration::from_millis(2500), info.utime);
+        assert_eq!(Duration::from_millis(1010), info.stime);
+        assert_eq!(19, info.nice);
+        assert_eq!(3, info.processor);
+        assert_eq!(5, info.rt_priority);
+        assert_eq!(Policy::Batch, info.policy);
+    }
+
+    #[test]
+    fn test_parse_stat_tricky_comm() {
+        for &comm in &["a) b", "(a) b)", ") 1 2 3 (", "", "x y z ) S"] {
+            let info = parse_stat(&stat(comm), 100).unwrap();
+            assert_eq!(comm, info.comm);
+            assert_eq!('S', info.state);
+            assert_eq!(19, info.nice);
+        }
+    }
+
+    #[test]
+    fn test_parse_stat_invalid() {
+        assert_eq!(ErrorKind::Other, parse_stat("6158 cat S 1 2", 100).unwrap_err().kind());
+        assert!(parse_stat("6158 (cat) S 1 2", 100).is_err());
+        let unknown = stat("cat").replace(" 17 3 5 3 ", " 17 3 5 42 ");
+        match parse_stat(&unknown, 100) {
+            Err(Error::UnknownPolicy(42)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_proc_sched_info() {
+        set_self_policy(Policy::Batch, 0).unwrap();
+        let info = proc_sched_info(Target::CurrentThread).unwrap();
+        set_self_policy(Policy::Other, 0).unwrap();
+        assert_eq!(Policy::Batch, info.policy);
+        assert_eq!(0, info.rt_priority);
+        assert_eq!('R', info.state);
+        assert!(proc_sched_info(Pid::this()).is_ok());
+        assert_eq!(ErrorKind::Other, proc_sched_info(i32::MAX).unwrap_err().kind());
+    }
+
+    const SCHED: &str = "cat (8034, #threads: 1)\n\
+        -------------------------------------------------------------------\n\
+        se.exec_start                                :       1779354.871600\n\
+        se.sum_exec_runtime                          :             3.000500\n\
+        se.nr_migrations                             :                    2\n\
+        nr_switches                                  :                   12\n\
+        nr_voluntary_switches                        :                    9\n\
+        nr_involuntary_switches                      :                    3\n\
+        policy                                       :                    0\n\
+        current_node=0, numa_group_id=0\n";
+
+    #[test]
+    fn test_parse_sched_stats() {
+        let mut stats = parse_schedstat("3000500 49531 12\n").unwrap();
+        assert_eq!(Duration::from_nanos(3_000_500), stats.run_time);
+        assert_eq!(Duration::from_nanos(49_531), stats.wait_time);
+        assert_eq!(12, stats.timeslices);
+        assert_eq!(None, stats.voluntary_switches);
+        parse_sched(SCHED, &mut stats);
+        assert_eq!(Some(9), stats.voluntary_switches);
+        assert_eq!(Some(3), stats.involuntary_switches);
+        assert_eq!(Some(2), stats.migrations);
+        assert!(parse_schedstat("1 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_sched_missing_fields() {
+        let mut stats = SchedStats::default();
+        parse_sched("cat (1, #threads: 1)\nnr_voluntary_switches : 4\n", &mut stats);
+        assert_eq!(Some(4), stats.voluntary_switches);
+        assert_eq!(None, stats.involuntary_switches);
+        assert_eq!(None, stats.migrations);
+    }
+
+    #[test]
+    fn test_sched_stats_delta() {
+        let earlier = SchedStats {
+            run_time: Duration::from_millis(5),
+            wait_time: Duration::from_millis(2),
+            timeslices: 4,
+            voluntary_switches: Some(3),
+            involuntary_switches: Some(1),
+            migrations: None,
+        };
+        let now = SchedStats {
+            run_time: Duration::from_millis(8),
+            wait_time: Duration::from_millis(1),
+            timeslices: 10,
+            voluntary_switches: Some(7),
+            involuntary_switches: Some(1),
+            migrations: Some(2),
+        };
+        let delta = now.delta(&earlier);
+        assert_eq!(Duration::from_millis(3), delta.run_time);
+        assert_eq!(Duration::from_millis(0), delta.wait_time);
+        assert_eq!(6, delta.timeslices);
+        assert_eq!(Some(4), delta.voluntary_switches);
+        assert_eq!(Some(0), delta.involuntary_switches);
+        assert_eq!(None, delta.migrations);
+    }
+
+    #[test]
+    fn test_sched_stats() {
+        let earlier = sched_stats(Target::CurrentThread).unwrap();
+        let mut sum = 0u64;
+        for i in 0..1_000_000u64 {
+            sum = sum.wrapping_add(i * i);
+        }
+        assert!(sum > 0);
+        let later = sched_stats(Target::CurrentThread).unwrap();
+        assert!(later.run_time > earlier.run_time);
+        assert!(sched_stats(Pid::this()).is_ok());
+    }
+
+    #[test]
+    fn test_parse_ctxt_switches() {
+        let status = "Name:\tcat\nThreads:\t1\nvoluntary_ctxt_switches:\t9\n\
+                      nonvoluntary_ctxt_switches:\t3\n";
+        let switches = CtxSwitches { voluntary: Some(9), involuntary: Some(3) };
+        assert_eq!(switches, parse_ctxt_switches(status));
+        assert_eq!(CtxSwitches::default(), parse_ctxt_switches("Name:\tcat\n"));
+        let earlier = CtxSwitches { voluntary: Some(4), involuntary: None };
+        assert_eq!(CtxSwitches { voluntary: Some(5), involuntary: None }, switches.delta(&earlier));
+    }
+
+    #[test]
+    fn test_context_switches() {
+        let before = context_switches(Target::CurrentThread).unwrap();
+        ::std::thread::sleep(Duration::from_millis(1));
+        let after = context_switches(Tid::current()).unwrap();
+        assert!(after.delta(&before).voluntary.unwrap() >= 1);
+        assert_eq!(ErrorKind::Other, context_switches(i32::MAX).unwrap_err().kind());
+    }
+}
@@ -0,0 +1,210 @@
+//! Preset combinations of scheduling settings for common kinds of work
+use cpuset::CpuSet;
+use error::{Error, ErrorKind};
+use ioprio::{set_io_priority, IoClass};
+use resource::{set_priority, Which, Who};
+use sched::{set_affinity, set_policy, validate_priority, Policy};
+use target::Target;
+
+/// A preset of scheduling settings, see `apply_profile`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Profile {
+    /// Run only when nothing else wants the CPU or the disks: `Policy::Idle`, nice value 19 and
+    /// the idle I/O class
+    Background,
+    /// Bulk work that does not need low latency: `Policy::Batch` and nice value 10
+    Batch,
+    /// Work that needs low latency: `Policy::Fifo` with `priority`, and the cpu affinity set to
+    /// `cpus` if given. Without the privileges for `Policy::Fifo`, `Policy::Other` with nice
+    /// value -10 is used instead, or the nice value is left alone if lowering it is not allowed
+    /// either.
+    LowLatency {
+        /// The real-time priority
+        priority: i32,
+        /// The CPUs to pin to
+        cpus: Option<CpuSet>,
+    },
+}
+
+/// The settings `apply_profile` applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileReport {
+    /// The policy and priority that were set
+    pub policy: (Policy, i32),
+    /// The nice value that was set, `None` if it was not allowed or not part of the profile
+    pub nice: Option<i32>,
+    /// Whether the I/O priority was set to the idle class
+    pub idle_io: bool,
+    /// The cpu affinity that was set
+    pub affinity: Option<CpuSet>,
+    /// Whether `Policy::Fifo` was not allowed and the fallback of `Profile::LowLatency` was used
+    pub downgraded: bool,
+}
+
+/// Apply a profile to a thread, in the order policy, nice value, I/O priority, affinity.
+///
+/// Each profile documents its settings. A nice value that is not allowed is skipped and a
+/// real-time policy that is not allowed falls back, as described in `Profile`. The returned
+/// report tells what was applied. Other errors are returned, leaving the settings applied
+/// before them.
+pub fn apply_profile<T: Into<Target>>(target: T, profile: &Profile)
+                                      -> Result<ProfileReport, Error> {
+    let target = target.into();
+    match *profile {
+        Profile::Background => {
+            set_policy(target, Policy::Idle, 0)?;
+            Ok(ProfileReport {
+                policy: (Policy::Idle, 0),
+                nice: try_nice(target, 19)?,
+                idle_io: set_idle_io_priority(target)?,
+                affinity: None,
+                downgraded: false,
+            })
+        }
+        Profile::Batch => {
+            set_policy(target, Policy::Batch, 0)?;
+            Ok(ProfileReport {
+                policy: (Policy::Batch, 0),
+                nice: try_nice(target, 10)?,
+                idle_io: false,
+                affinity: None,
+                downgraded: false,
+            })
+        }
+        Profile::LowLatency { priority, ref cpus } => {
+            validate_priority(Policy::Fifo, priority)?;
+            let mut report = low_latency(priority, |policy, priority| {
+                set_policy(target, policy, priority)
+            }, |nice| try_nice(target, nice))?;
+            if let Some(ref cpus) = *cpus {
+                set_affinity(target, cpus)?;
+                report.affinity = Some(cpus.clone());
+            }
+            Ok(report)
+        }
+    }
+}
+
+/// Set `Policy::Fifo`, falling back to `Policy::Other` with nice value -10 without the
+/// privileges for it.
+fn low_latency<P, N>(priority: i32, mut set_policy: P, mut set_nice: N)
+                     -> Result<ProfileReport, Error>
+    where P: FnMut(Policy, i32) -> Result<(), Error>,
+          N: FnMut(i32) -> Result<Option<i32>, Error>
+{
+    let mut report = ProfileReport {
+        policy: (Policy::Fifo, priority),
+        nice: None,
+        idle_io: false,
+        affinity: None,
+        downgraded: false,
+    };
+    match set_policy(Policy::Fifo, priority) {
+        Ok(()) => {}
+        Err(ref error) if error.kind() == ErrorKind::PermissionDenied => {
+            set_policy(Policy::Other, 0)?;
+            report.policy = (Policy::Other, 0);
+            report.nice = set_nice(-10)?;
+            report.downgraded = true;
+        }
+        Err(error) => return Err(error),
+    }
+    Ok(report)
+}
+
+/// Set the nice value, returning `None` if it is not allowed.
+fn try_nice(target: Target, nice: i32) -> Result<Option<i32>, Error> {
+    match set_priority(Which::Process, target.as_raw(), nice) {
+        Ok(()) => Ok(Some(nice)),
+        Err(ref error) if error.kind() == ErrorKind::PermissionDenied => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Set the I/O priority to the idle class, returning `false` if the kernel does not support it.
+fn set_idle_io_priority(target: Target) -> Result<bool, Error> {
+    match set_io_priority(Who::Process(target.as_raw()), IoClass::Idle, 0) {
+        Ok(()) => Ok(true),
+        Err(ref error) if error.kind() == ErrorKind::Unsupported => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::{EINVAL, EPERM};
+    use std::thread;
+    use super::{apply_profile, low_latency, Profile};
+    use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+    use resource::{get_self_priority, Which};
+    use sched::{get_self_affinity_auto, get_self_policy_and_priority, Policy};
+    use target::Target;
+
+    #[test]
+    fn test_apply_profile() {
+        let (report, policy, nice) = thread::spawn(|| {
+            let report = apply_profile(Target::CurrentThread, &Profile::Background).unwrap();
+            (report, get_self_policy_and_priority().unwrap(),
+             get_self_priority(Which::Process).unwrap())
+        }).join().unwrap();
+        assert_eq!((Policy::Idle, 0), report.policy);
+        assert_eq!((Policy::Idle, 0), policy);
+        assert_eq!(Some(19), report.nice);
+        assert_eq!(19, nice);
+        assert!(report.idle_io);
+
+        let (report, policy) = thread::spawn(|| {
+            let report = apply_profile(Target::CurrentThread, &Profile::Batch).unwrap();
+            (report, get_self_policy_and_priority().unwrap())
+        }).join().unwrap();
+        assert_eq!((Policy::Batch, 0), policy);
+        assert_eq!(Some(10), report.nice);
+    }
+
+    #[test]
+    fn test_apply_low_latency_profile() {
+        let profile = Profile::LowLatency { priority: 10, cpus: Some(CpuSet::single(0)) };
+        let (report, policy, affinity) = thread::spawn(move || {
+            let report = apply_profile(Target::CurrentThread, &profile);
+            (report, get_self_policy_and_priority().unwrap(), get_self_affinity_auto().unwrap())
+        }).join().unwrap();
+        let report = report.unwrap();
+        assert_eq!(report.policy, policy);
+        assert_eq!(Some(CpuSet::single(0)), report.affinity);
+        assert_eq!(CpuSet::single(0), affinity);
+        assert_eq!(report.downgraded, report.policy != (Policy::Fifo, 10));
+
+        let profile = Profile::LowLatency { priority: 0, cpus: None };
+        match apply_profile(Target::CurrentThread, &profile) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_low_latency_fallback() {
+        let mut policies = Vec::new();
+        let report = low_latency(10, |policy, priority| {
+            policies.push((policy, priority));
+            match policy {
+                Policy::Fifo => Err(Error::from_raw_os_error(EPERM)),
+                _ => Ok(()),
+            }
+        }, |nice| Ok(Some(nice))).unwrap();
+        assert_eq!(vec![(Policy::Fifo, 10), (Policy::Other, 0)], policies);
+        assert_eq!((Policy::Other, 0), report.policy);
+        assert_eq!(Some(-10), report.nice);
+        assert!(report.downgraded);
+
+        let report = low_latency(10, |policy, _| match policy {
+            Policy::Fifo => Err(Error::from_raw_os_error(EPERM)),
+            _ => Ok(()),
+        }, |_| Ok(None)).unwrap();
+        assert_eq!(None, report.nice);
+
+        let error = low_latency(10, |_, _| Err(Error::from_raw_os_error(EINVAL)),
+                                |nice| Ok(Some(nice))).unwrap_err();
+        assert_eq!(ErrorKind::InvalidArgument, error.kind());
+    }
+}
@@ -0,0 +1,289 @@
+//! Checks whether the calling process may use the real-time scheduling policies and lower nice
+//! values, and how the kernel runs real-time threads
+use libc::{uname, utsname};
+use std::cmp;
+use std::ffi::CStr;
+use std::fs;
+use std::io;
+use std::mem;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use error::Error;
+use resource::{get_rlimit, get_self_priority, Resource, Which};
+use sched::{get_priority_max, validate_priority, Policy};
+
+/// Bit of `CAP_SYS_NICE` in the capability sets of `/proc/<pid>/status`.
+const CAP_SYS_NICE: u32 = 23;
+
+/// Check whether the calling process may set `Policy::Fifo` or `Policy::RoundRobin` with
+/// `priority`, without trying it.
+///
+/// Either `CAP_SYS_NICE` in the effective capabilities or a soft `RLIMIT_RTPRIO` of at least
+/// `priority` allows it, see `has_cap_sys_nice` and `rtprio_limit`. Returns an error if the
+/// priority is out of range for `Policy::Fifo`. Other restrictions, like a cgroup without
+/// real-time runtime, are not checked.
+pub fn can_set_realtime(priority: i32) -> Result<bool, Error> {
+    validate_priority(Policy::Fifo, priority)?;
+    if has_cap_sys_nice()? {
+        return Ok(true);
+    }
+    Ok(rtprio_limit()?.map_or(true, |limit| priority as u64 <= limit))
+}
+
+/// Get the soft `RLIMIT_RTPRIO` of the calling process, the highest real-time priority it may
+/// set without `CAP_SYS_NICE`, where `None` means unlimited.
+pub fn rtprio_limit() -> Result<Option<u64>, Error> {
+    Ok(get_rlimit(Resource::RtPrio)?.soft)
+}
+
+/// Get the highest real-time priority `RLIMIT_RTPRIO` allows the calling process to set, where
+/// 0 means the real-time policies are not allowed at all.
+///
+/// An unlimited or too large soft limit gives the highest priority of `Policy::Fifo`.
+/// `CAP_SYS_NICE` bypasses the limit, which is not taken into account here, see
+/// `can_set_realtime`.
+pub fn max_rt_priority() -> Result<i32, Error> {
+    Ok(rt_priority_from_rlimit(rtprio_limit()?, get_priority_max(&Policy::Fifo)?))
+}
+
+/// Get the lowest nice value `RLIMIT_NICE` allows the calling process to set, `20 - limit`
+/// in the range `-20..=19`.
+///
+/// An unlimited limit gives -20, and a limit of 0 or 1 gives 19, since raising the nice value
+/// is always allowed. `CAP_SYS_NICE` bypasses the limit and a nice value that is already lower
+/// can be kept, which are not taken into account here, see `allowed_nice_range`.
+pub fn min_allowed_nice() -> Result<i32, Error> {
+    Ok(nice_from_rlimit(get_rlimit(Resource::Nice)?.soft))
+}
+
+/// Get the range of nice values the calling thread may set.
+///
+/// Raising the nice value is always allowed. Lowering it is allowed down to `20 - RLIMIT_NICE`,
+/// or to -20 with `CAP_SYS_NICE`, see `has_cap_sys_nice`. The range starts at the current
+/// nice value if that is lower.
+pub fn allowed_nice_range() -> Result<RangeInclusive<i32>, Error> {
+    if has_cap_sys_nice()? {
+        return Ok(-20..=19);
+    }
+    Ok(cmp::min(get_self_priority(Which::Process)?, min_allowed_nice()?)..=19)
+}
+
+/// Convert a soft `RLIMIT_RTPRIO` to a priority, capped at `max`.
+fn rt_priority_from_rlimit(limit: Option<u64>, max: i32) -> i32 {
+    limit.map_or(max, |limit| cmp::min(limit, max as u64) as i32)
+}
+
+/// Convert a soft `RLIMIT_NICE` to the lowest nice value it allows, the kernel allows a nice
+/// value `nice` if `20 - nice` is at most the limit.
+fn nice_from_rlimit(limit: Option<u64>) -> i32 {
+    match limit {
+        Some(limit) => 20 - limit.clamp(1, 40) as i32,
+        None => -20,
+    }
+}
+
+/// Check whether `CAP_SYS_NICE` is in the effective capabilities of the calling process, read
+/// from the `CapEff` line of `/proc/self/status`.
+pub fn has_cap_sys_nice() -> Result<bool, Error> {
+    let status = fs::read_to_string("/proc/self/status")?;
+    match parse_cap_eff(&status) {
+        Some(caps) => Ok(caps & (1 << CAP_SYS_NICE) != 0),
+        None => {
+            let message = "no CapEff line in /proc/self/status";
+            Err(Error::Io(io::Error::new(io::ErrorKind::InvalidData, message)))
+        }
+    }
+}
+
+/// Parse the hexadecimal `CapEff` line of a `/proc/<pid>/status` file.
+fn parse_cap_eff(status: &str) -> Option<u64> {
+    status.lines()
+        .find(|line| line.starts_with("CapEff:"))
+        .and_then(|line| u64::from_str_radix(line["CapEff:".len()..].trim(), 16).ok())
+}
+
+/// Files limiting the CPU time of the real-time policies, in microseconds.
+const RT_RUNTIME: &str = "/proc/sys/kernel/sched_rt_runtime_us";
+const RT_PERIOD: &str = "/proc/sys/kernel/sched_rt_period_us";
+
+/// Check whether the kernel is built with `PREEMPT_RT`, making almost all of it preemptible.
+///
+/// Checks `/sys/kernel/realtime`, and the `PREEMPT_RT` flag in the version reported by `uname`
+/// for kernels without that file.
+pub fn is_realtime_kernel() -> bool {
+    let realtime = fs::read_to_string("/sys/kernel/realtime").ok();
+    parse_realtime(realtime.as_deref(), kernel_version)
+}
+
+/// Get the version reported by `uname`, e.g. `#1 SMP PREEMPT_RT Tue Jan 2 10:00:00 UTC 2024`,
+/// empty if it fails.
+fn kernel_version() -> String {
+    let mut name: utsname = unsafe { mem::zeroed() };
+    match unsafe { uname(&mut name) } {
+        0 => unsafe { CStr::from_ptr(name.version.as_ptr()) }.to_string_lossy().into_owned(),
+        _ => String::new(),
+    }
+}
+
+/// Decide whether the kernel is built with `PREEMPT_RT` from the contents of
+/// `/sys/kernel/realtime` if it exists, and otherwise from the kernel version.
+fn parse_realtime<F: FnOnce() -> String>(realtime: Option<&str>, version: F) -> bool {
+    match realtime {
+        Some(realtime) => realtime.trim() == "1",
+        None => version().split_whitespace().any(|flag| flag == "PREEMPT_RT"),
+    }
+}
+
+/// Get the real-time throttling of the system, as `(runtime, period)`: in every period, the
+/// threads using `Policy::Fifo` and `Policy::RoundRobin` run at most for the runtime, leaving
+/// the rest to the other policies. The default is 0.95 seconds per second.
+///
+/// Read from `/proc/sys/kernel/sched_rt_runtime_us` and `sched_rt_period_us`. Returns `None`
+/// if throttling is disabled, with a runtime of -1.
+pub fn rt_throttling() -> Result<Option<(Duration, Duration)>, Error> {
+    parse_throttling(&fs::read_to_string(RT_RUNTIME)?, &fs::read_to_string(RT_PERIOD)?)
+}
+
+/// Set the real-time throttling of the system, see `rt_throttling`, where `None` disables it.
+///
+/// The runtime can not exceed the period. Requires root, otherwise this fails with
+/// `ErrorKind::PermissionDenied`.
+pub fn set_rt_throttling(throttling: Option<(Duration, Duration)>) -> Result<(), Error> {
+    let (runtime, period) = match throttling {
+        Some((runtime, period)) if runtime <= period => (runtime.as_micros(), period.as_micros()),
+        Some((runtime, period)) => {
+            return Err(Error::InvalidArgument(format!("runtime {:?} exceeds period {:?}",
+                                                      runtime, period)))
+        }
+        None => return Ok(fs::write(RT_RUNTIME, "-1")?),
+    };
+    // The kernel rejects each write that would leave the runtime above the period
+    let current_period: u128 = fs::read_to_string(RT_PERIOD)?.trim().parse().unwrap_or(0);
+    if runtime > current_period {
+        fs::write(RT_PERIOD, period.to_string())?;
+        fs::write(RT_RUNTIME, runtime.to_string())?;
+    } else {
+        fs::write(RT_RUNTIME, runtime.to_string())?;
+        fs::write(RT_PERIOD, period.to_string())?;
+    }
+    Ok(())
+}
+
+fn parse_throttling(runtime: &str, period: &str) -> Result<Option<(Duration, Duration)>, Error> {
+    let invalid = || {
+        let message = format!("invalid real-time throttling {:?} {:?}", runtime, period);
+        Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+    };
+    let runtime: i64 = runtime.trim().parse().map_err(|_| invalid())?;
+    let period: u64 = period.trim().parse().map_err(|_| invalid())?;
+    match runtime {
+        -1 => Ok(None),
+        runtime if runtime >= 0 => {
+            Ok(Some((Duration::from_micros(runtime as u64), Duration::from_micros(period))))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{allowed_nice_range, can_set_realtime, has_cap_sys_nice, max_rt_priority,
+                min_allowed_nice, nice_from_rlimit, parse_cap_eff, parse_realtime,
+                parse_throttling, rt_priority_from_rlimit, rt_throttling, rtprio_limit,
+                set_rt_throttling};
+    use error::{Error, ErrorKind};
+
+    #[test]
+    fn test_parse_cap_eff() {
+        let status = "Name:\tcat\nCapPrm:\t0000000000000000\nCapEff:\t0000000000800000\n";
+        assert_eq!(Some(1 << 23), parse_cap_eff(status));
+        assert_eq!(Some(0x1ff_ffff_ffff), parse_cap_eff("CapEff:\t000001ffffffffff"));
+        assert_eq!(None, parse_cap_eff("CapPrm:\t0000000000800000\n"));
+        assert_eq!(None, parse_cap_eff("CapEff:\tnot hex\n"));
+    }
+
+    #[test]
+    fn test_can_set_realtime() {
+        let allowed = has_cap_sys_nice().unwrap() ||
+                      rtprio_limit().unwrap().map_or(true, |limit| limit >= 1);
+        assert_eq!(allowed, can_set_realtime(1).unwrap());
+        match can_set_realtime(0) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_throttling() {
+        assert_eq!(Some((Duration::from_millis(950), Duration::from_secs(1))),
+                   parse_throttling("950000\n", "1000000\n").unwrap());
+        assert_eq!(None, parse_throttling("-1\n", "1000000\n").unwrap());
+        for &(runtime, period) in &[("-2", "1000000"), ("x", "1000000"), ("950000", "")] {
+            assert_eq!(ErrorKind::Other, parse_throttling(runtime, period).unwrap_err().kind());
+        }
+    }
+
+    #[test]
+    fn test_rt_throttling() {
+        let throttling = rt_throttling().unwrap();
+        if let Some((runtime, period)) = throttling {
+            assert!(runtime <= period);
+        }
+        match set_rt_throttling(Some((Duration::from_secs(2), Duration::from_secs(1)))) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        // Writing the current values back changes nothing
+        match set_rt_throttling(throttling) {
+            Ok(()) => assert_eq!(throttling, rt_throttling().unwrap()),
+            Err(error) => assert_eq!(ErrorKind::PermissionDenied, error.kind()),
+        }
+    }
+
+    #[test]
+    fn test_parse_realtime() {
+        let rt = || "#1 SMP PREEMPT_RT Tue Jan 2 10:00:00 UTC 2024".to_string();
+        let dynamic = || "#1 SMP PREEMPT_DYNAMIC Tue Jan 2 10:00:00 UTC 2024".to_string();
+        assert!(parse_realtime(Some("1\n"), dynamic));
+        assert!(!parse_realtime(Some("0\n"), rt));
+        assert!(parse_realtime(None, rt));
+        assert!(!parse_realtime(None, dynamic));
+        assert!(!parse_realtime(None, String::new));
+    }
+
+    #[test]
+    fn test_allowed_nice_range() {
+        let range = allowed_nice_range().unwrap();
+        assert_eq!(19, *range.end());
+        if has_cap_sys_nice().unwrap() {
+            assert_eq!(-20, *range.start());
+        }
+        assert!(*range.start() >= -20);
+    }
+
+    #[test]
+    fn test_rlimit_conversions() {
+        assert_eq!(99, rt_priority_from_rlimit(None, 99));
+        assert_eq!(99, rt_priority_from_rlimit(Some(u64::MAX), 99));
+        assert_eq!(50, rt_priority_from_rlimit(Some(50), 99));
+        assert_eq!(0, rt_priority_from_rlimit(Some(0), 99));
+
+        assert_eq!(-20, nice_from_rlimit(None));
+        assert_eq!(-20, nice_from_rlimit(Some(40)));
+        assert_eq!(-20, nice_from_rlimit(Some(u64::MAX)));
+        assert_eq!(0, nice_from_rlimit(Some(20)));
+        assert_eq!(19, nice_from_rlimit(Some(1)));
+        assert_eq!(19, nice_from_rlimit(Some(0)));
+    }
+
+    #[test]
+    fn test_rlimit_accessors() {
+        let max = max_rt_priority().unwrap();
+        assert!((0..=99).contains(&max));
+        assert_eq!(max >= 1 || has_cap_sys_nice().unwrap(), can_set_realtime(1).unwrap());
+        let min = min_allowed_nice().unwrap();
+        assert!((-20..=19).contains(&min));
+        assert!(*allowed_nice_range().unwrap().start() <= min);
+    }
+}
@@ -1,55 +1,720 @@
-//! Set and get program scheduling priority
+//! Set and get program scheduling priority and resource limits
 use errno::{Errno, errno, set_errno};
+use error::Error;
 use libc::{PRIO_PROCESS,PRIO_PGRP,PRIO_USER,setpriority,getpriority, id_t};
+use libc::{c_int, getrlimit, pid_t, rlim_t, rlimit, rusage, setrlimit, timeval, uid_t,
+           EINTR, RLIM_INFINITY, RUSAGE_CHILDREN, RUSAGE_SELF};
+#[cfg(target_os = "linux")]
+use libc::RUSAGE_THREAD;
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+use std::ops::RangeInclusive;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ExitStatus};
+use std::time::Duration;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use libc::prlimit;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::ptr;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use target::Pid;
 
 /// Which identifier type to use (`pid`, `gid`, or `uid`)
+///
+/// With the `serde` feature, serializes as `"process"`, `"group"` or `"user"`.
 #[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
 pub enum Which {
     Process,
     Group,
     User,
 }
 
+/// The processes whose scheduling priority is set, see `set_priority_for`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Who {
+    /// A process by its `pid`, where 0 is the calling process. On Linux this is a thread.
+    Process(pid_t),
+    /// Every process in a process group by its `pgid`, where 0 is the group of the calling
+    /// process
+    Group(pid_t),
+    /// Every process of a user by its `uid`, where 0 is the real user of the calling process
+    User(uid_t),
+}
+
+impl Who {
+    /// Select the processes like the `which` and `who` arguments of `set_priority`, where `who`
+    /// is reinterpreted as unsigned for `Which::User`.
+    pub fn from_which(which: Which, who: i32) -> Who {
+        match which {
+            Which::Process => Who::Process(who),
+            Which::Group => Who::Group(who),
+            Which::User => Who::User(who as uid_t),
+        }
+    }
+
+    /// Get the `which` and `who` arguments of `setpriority` and `getpriority`.
+    fn to_raw(self) -> (c_int, id_t) {
+        match self {
+            Who::Process(pid) => (PRIO_PROCESS as c_int, pid as id_t),
+            Who::Group(pgid) => (PRIO_PGRP as c_int, pgid as id_t),
+            Who::User(uid) => (PRIO_USER as c_int, uid as id_t),
+        }
+    }
+}
+
+/// A nice value, the scheduling priority set with `set_priority`, in the range of the system
+///
+/// The range is `-NZERO..=NZERO - 1`, where `NZERO` is 20 on Linux and most other systems, see
+/// `Nice::range`. Lower values get more CPU time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Nice(i32);
+
+impl Nice {
+    /// The lowest nice value, with the highest priority, for an `NZERO` of 20
+    pub const MIN: Nice = Nice(-20);
+    /// The highest nice value, with the lowest priority, for an `NZERO` of 20
+    pub const MAX: Nice = Nice(19);
+    /// The nice value processes start with
+    pub const DEFAULT: Nice = Nice(0);
+
+    /// Create a `Nice`, returning an error if `nice` is out of the range of the system.
+    pub fn new(nice: i32) -> Result<Nice, Error> {
+        let range = Nice::range();
+        match nice {
+            nice if range.contains(&nice) => Ok(Nice(nice)),
+            nice => Err(Error::InvalidArgument(format!("nice value {} is out of the range {}..={}",
+                                                       nice, range.start(), range.end()))),
+        }
+    }
+
+    /// Get the range of nice values of the system, `-NZERO..=NZERO - 1`.
+    ///
+    /// `NZERO` is read with `sysconf` where available, and 20 otherwise.
+    pub fn range() -> RangeInclusive<i32> {
+        let nzero = nzero();
+        -nzero..=nzero - 1
+    }
+
+    /// Get the nice value.
+    pub fn get(self) -> i32 {
+        self.0
+    }
+
+    /// Add `delta`, staying in the range of the system.
+    pub fn saturating_add(self, delta: i32) -> Nice {
+        let range = Nice::range();
+        Nice(cmp::max(*range.start(), cmp::min(self.0.saturating_add(delta), *range.end())))
+    }
+
+    /// Subtract `delta`, staying in the range of the system.
+    pub fn saturating_sub(self, delta: i32) -> Nice {
+        self.saturating_add(delta.saturating_neg())
+    }
+}
+
+impl Default for Nice {
+    fn default() -> Nice {
+        Nice::DEFAULT
+    }
+}
+
+impl From<Nice> for i32 {
+    fn from(nice: Nice) -> i32 {
+        nice.0
+    }
+}
+
+impl TryFrom<i32> for Nice {
+    type Error = Error;
+
+    fn try_from(nice: i32) -> Result<Nice, Error> {
+        Nice::new(nice)
+    }
+}
+
+impl fmt::Display for Nice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Get `NZERO`, the default nice value, which is 0, as an offset from the lowest.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+fn nzero() -> i32 {
+    match unsafe { libc::sysconf(libc::_SC_NZERO) } {
+        nzero if nzero > 0 => nzero as i32,
+        _ => 20,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "emscripten")))]
+fn nzero() -> i32 {
+    20
+}
+
 /// Set the scheduling priority for the `Which` of the calling process
 ///
 /// Priorities are usually in the range of -20..19, dependent on your system.
-pub fn set_self_priority(which: Which, priority: i32) -> Result<(), ()> {
+pub fn set_self_priority<P: Into<i32>>(which: Which, priority: P) -> Result<(), Error> {
     set_priority(which, 0, priority)
 }
 
 /// Set the scheduling priority for the selected identifier (`pid`, `gid`, or `uid`)
 ///
-/// Priorities are usually in the range of -20..19, dependent on your system.
-pub fn set_priority(which: Which, who: i32, priority: i32) -> Result<(), ()> {
-    let c_which = match which {
-        Which::Process => PRIO_PROCESS,
-        Which::Group => PRIO_PGRP,
-        Which::User => PRIO_USER,
-    };
+/// Priorities are usually in the range of -20..19, dependent on your system. Group and user ids
+/// above `i32::MAX` are passed as negative numbers, see `set_priority_for` to avoid that.
+///
+/// On Linux with autogrouping, the priority only weighs a process against the processes of its
+/// session. See `set_autogroup_nice` to weigh the session against the others.
+pub fn set_priority<P: Into<i32>>(which: Which, who: i32, priority: P) -> Result<(), Error> {
+    set_priority_for(Who::from_which(which, who), priority)
+}
 
-    match unsafe { setpriority(c_which as u32, who as id_t, priority) } {
+/// Set the scheduling priority for a process, process group or user
+///
+/// Priorities are usually in the range of -20..19, dependent on your system. See
+/// `set_priority`.
+pub fn set_priority_for<P: Into<i32>>(who: Who, priority: P) -> Result<(), Error> {
+    let (which, id) = who.to_raw();
+    match unsafe { setpriority(which as _, id, priority.into()) } {
         0 => Ok(()),
-        _ => Err(()),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Set the scheduling priority for the selected identifier, like `set_priority`, and check that
+/// it was applied.
+///
+/// The system clamps priorities out of its range instead of failing, which returns
+/// `Error::Clamped` with the priority that was applied. For `Which::Group` and `Which::User`,
+/// the highest priority of the selected processes is compared, see `get_priority`.
+pub fn set_priority_checked<P: Into<i32>>(which: Which, who: i32, priority: P)
+                                          -> Result<(), Error> {
+    let priority = priority.into();
+    set_priority(which, who, priority)?;
+    match get_priority(which, who)? {
+        actual if actual != priority => Err(Error::Clamped { requested: priority, actual }),
+        _ => Ok(()),
     }
 }
 
+/// Change the scheduling priority of the calling process by `delta`, like `nice(2)`, returning
+/// the new priority. On Linux this changes only the calling thread.
+///
+/// A priority out of the range of the system is clamped to it and returns `Error::Clamped`,
+/// see `set_priority_checked`. Another thread changing the priority of this one in between
+/// reading and setting it is not detected.
+pub fn adjust_self_priority(delta: i32) -> Result<i32, Error> {
+    let priority = get_priority(Which::Process, 0)?.saturating_add(delta);
+    set_priority_checked(Which::Process, 0, priority)?;
+    Ok(priority)
+}
+
 /// Get the scheduling priority for the `Which` of the calling process
-pub fn get_self_priority(which: Which) -> Result<i32, ()> {
+pub fn get_self_priority(which: Which) -> Result<i32, Error> {
     get_priority(which, 0)
 }
 
 /// Get the scheduling priority for the selected identifier (`pid`, `gid`, or `uid`)
-pub fn get_priority(which: Which, who: i32) -> Result<i32, ()> {
-    let c_which = match which {
-        Which::Process => PRIO_PROCESS,
-        Which::Group => PRIO_PGRP,
-        Which::User => PRIO_USER,
-    };
+///
+/// For `Which::Group` and `Which::User`, the highest priority of the selected processes is
+/// returned, which is the lowest value. See `get_priority_for`.
+pub fn get_priority(which: Which, who: i32) -> Result<i32, Error> {
+    get_priority_for(Who::from_which(which, who))
+}
 
+/// Get the scheduling priority for a process, process group or user
+///
+/// For `Who::Group` and `Who::User`, the highest priority of the selected processes is
+/// returned, which is the lowest value.
+pub fn get_priority_for(who: Who) -> Result<i32, Error> {
+    let (which, id) = who.to_raw();
     set_errno(Errno(0));
-    let priority = unsafe { getpriority(c_which as u32, who as id_t) };
+    let priority = unsafe { getpriority(which as _, id) };
     match errno().0 {
         0 => Ok(priority),
-        _ => Err(()),
+        code => Err(Error::from_raw_os_error(code)),
+    }
+}
+
+/// A resource limited with `set_rlimit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Resource {
+    /// The size of the address space, in bytes (`RLIMIT_AS`)
+    AddressSpace,
+    /// The size of core dumps, in bytes (`RLIMIT_CORE`)
+    Core,
+    /// The CPU time, in seconds (`RLIMIT_CPU`)
+    Cpu,
+    /// The size of the data segment, in bytes (`RLIMIT_DATA`)
+    Data,
+    /// The size of created files, in bytes (`RLIMIT_FSIZE`)
+    FileSize,
+    /// The number of open file descriptors, one more than the highest (`RLIMIT_NOFILE`)
+    NoFile,
+    /// The size of the stack of the main thread, in bytes (`RLIMIT_STACK`)
+    Stack,
+    /// The memory locked into RAM, in bytes (`RLIMIT_MEMLOCK`)
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos",
+              target_os = "freebsd"))]
+    MemLock,
+    /// The number of processes of the user (`RLIMIT_NPROC`)
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos",
+              target_os = "freebsd"))]
+    NProc,
+    /// The resident set size, in bytes, not enforced by current kernels (`RLIMIT_RSS`)
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos",
+              target_os = "freebsd"))]
+    Rss,
+    /// The lowest nice value, as `20 - nice` (`RLIMIT_NICE`)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Nice,
+    /// The highest real-time priority (`RLIMIT_RTPRIO`)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    RtPrio,
+    /// The CPU time a real-time thread may use without blocking, in microseconds
+    /// (`RLIMIT_RTTIME`)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    RtTime,
+    /// The bytes in POSIX message queues of the user (`RLIMIT_MSGQUEUE`)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    MsgQueue,
+    /// The number of pending signals of the user (`RLIMIT_SIGPENDING`)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    SigPending,
+}
+
+impl Resource {
+    pub(crate) fn to_c_int(self) -> c_int {
+        (match self {
+            Resource::AddressSpace => libc::RLIMIT_AS,
+            Resource::Core => libc::RLIMIT_CORE,
+            Resource::Cpu => libc::RLIMIT_CPU,
+            Resource::Data => libc::RLIMIT_DATA,
+            Resource::FileSize => libc::RLIMIT_FSIZE,
+            Resource::NoFile => libc::RLIMIT_NOFILE,
+            Resource::Stack => libc::RLIMIT_STACK,
+            #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos",
+                      target_os = "freebsd"))]
+            Resource::MemLock => libc::RLIMIT_MEMLOCK,
+            #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos",
+                      target_os = "freebsd"))]
+            Resource::NProc => libc::RLIMIT_NPROC,
+            #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos",
+                      target_os = "freebsd"))]
+            Resource::Rss => libc::RLIMIT_RSS,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Resource::Nice => libc::RLIMIT_NICE,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Resource::RtPrio => libc::RLIMIT_RTPRIO,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Resource::RtTime => libc::RLIMIT_RTTIME,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Resource::MsgQueue => libc::RLIMIT_MSGQUEUE,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Resource::SigPending => libc::RLIMIT_SIGPENDING,
+        }) as c_int
+    }
+}
+
+/// The soft and hard limit of a `Resource`, where `None` means unlimited (`RLIM_INFINITY`)
+///
+/// The soft limit is enforced, and can be raised up to the hard limit. Raising the hard limit
+/// requires `CAP_SYS_RESOURCE` on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rlimit {
+    /// The limit that is enforced
+    pub soft: Option<u64>,
+    /// The highest value of the soft limit
+    pub hard: Option<u64>,
+}
+
+impl Rlimit {
+    #[allow(clippy::unnecessary_cast)]
+    pub(crate) fn from_raw(limit: rlimit) -> Rlimit {
+        let convert = |value: rlim_t| match value {
+            RLIM_INFINITY => None,
+            value => Some(value as u64),
+        };
+        Rlimit { soft: convert(limit.rlim_cur), hard: convert(limit.rlim_max) }
+    }
+
+    pub(crate) fn to_raw(self) -> rlimit {
+        let convert = |value: Option<u64>| value.map_or(RLIM_INFINITY, |value| value as rlim_t);
+        rlimit { rlim_cur: convert(self.soft), rlim_max: convert(self.hard) }
+    }
+}
+
+/// Get the limit of a resource for the calling process
+pub fn get_rlimit(resource: Resource) -> Result<Rlimit, Error> {
+    let mut limit = rlimit { rlim_cur: 0, rlim_max: 0 };
+    match unsafe { getrlimit(resource.to_c_int() as _, &mut limit) } {
+        0 => Ok(Rlimit::from_raw(limit)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Set the limit of a resource for the calling process
+///
+/// Raising the hard limit without the privileges for it fails with
+/// `ErrorKind::PermissionDenied`, a soft limit above the hard limit with
+/// `ErrorKind::InvalidArgument`.
+pub fn set_rlimit(resource: Resource, limit: Rlimit) -> Result<(), Error> {
+    match unsafe { setrlimit(resource.to_c_int() as _, &limit.to_raw()) } {
+        0 => Ok(()),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Get the limit of a resource for any process, with `prlimit`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn get_rlimit_for(pid: Pid, resource: Resource) -> Result<Rlimit, Error> {
+    let mut old = rlimit { rlim_cur: 0, rlim_max: 0 };
+    match unsafe { prlimit(pid.as_raw(), resource.to_c_int() as _, ptr::null(), &mut old) } {
+        0 => Ok(Rlimit::from_raw(old)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Set the limit of a resource for any process, with `prlimit`, returning the previous limit
+///
+/// The previous limit is read in the same call, so no other change can come in between.
+/// Changing the limits of a process of another user requires `CAP_SYS_RESOURCE`, see also
+/// `set_rlimit`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn set_rlimit_for(pid: Pid, resource: Resource, limit: Rlimit) -> Result<Rlimit, Error> {
+    let mut old = rlimit { rlim_cur: 0, rlim_max: 0 };
+    match unsafe { prlimit(pid.as_raw(), resource.to_c_int() as _, &limit.to_raw(), &mut old) } {
+        0 => Ok(Rlimit::from_raw(old)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Whose resource usage `getrusage` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RusageTarget {
+    /// Every thread of the calling process (`RUSAGE_SELF`)
+    SelfProcess,
+    /// The children of the calling process that exited and were waited for, and their waited
+    /// for descendants (`RUSAGE_CHILDREN`)
+    Children,
+    /// The calling thread (`RUSAGE_THREAD`)
+    #[cfg(target_os = "linux")]
+    Thread,
+}
+
+/// Resource usage, see `getrusage`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rusage {
+    /// The time spent in user mode
+    pub utime: Duration,
+    /// The time spent in kernel mode
+    pub stime: Duration,
+    /// The highest resident set size, in bytes. For `RusageTarget::Children`, the highest of a
+    /// single child.
+    ///
+    /// Linux reports it in kilobytes and macOS in bytes, it is converted to bytes on both.
+    pub maxrss: u64,
+    /// The number of times the CPU was given up, e.g. to wait for I/O (`ru_nvcsw`)
+    pub voluntary_switches: u64,
+    /// The number of times it was preempted (`ru_nivcsw`)
+    pub involuntary_switches: u64,
+}
+
+impl Rusage {
+    pub(crate) fn from_raw(usage: &rusage) -> Rusage {
+        let duration = |time: timeval| {
+            Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+        };
+        // The unit of ru_maxrss is bytes on macOS and kilobytes elsewhere
+        let maxrss_unit = if cfg!(any(target_os = "macos", target_os = "ios")) { 1 } else { 1024 };
+        Rusage {
+            utime: duration(usage.ru_utime),
+            stime: duration(usage.ru_stime),
+            maxrss: usage.ru_maxrss as u64 * maxrss_unit,
+            voluntary_switches: usage.ru_nvcsw as u64,
+            involuntary_switches: usage.ru_nivcsw as u64,
+        }
+    }
+
+    /// Get the usage since an earlier sample of the same target.
+    ///
+    /// `maxrss` is a peak, not a sum, so the later value is kept. Counters that went backwards
+    /// are 0.
+    pub fn delta(&self, earlier: &Rusage) -> Rusage {
+        Rusage {
+            utime: self.utime.saturating_sub(earlier.utime),
+            stime: self.stime.saturating_sub(earlier.stime),
+            maxrss: self.maxrss,
+            voluntary_switches: self.voluntary_switches.saturating_sub(earlier.voluntary_switches),
+            involuntary_switches: self.involuntary_switches
+                .saturating_sub(earlier.involuntary_switches),
+        }
+    }
+}
+
+/// Get the resource usage of the calling process, its children or the calling thread
+pub fn getrusage(target: RusageTarget) -> Result<Rusage, Error> {
+    let who = match target {
+        RusageTarget::SelfProcess => RUSAGE_SELF,
+        RusageTarget::Children => RUSAGE_CHILDREN,
+        #[cfg(target_os = "linux")]
+        RusageTarget::Thread => RUSAGE_THREAD,
+    };
+    let mut usage: rusage = unsafe { mem::zeroed() };
+    match unsafe { libc::getrusage(who, &mut usage) } {
+        0 => Ok(Rusage::from_raw(&usage)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Wait for a child to exit, like `Child::wait`, and get its resource usage with `wait4`
+///
+/// The usage includes the descendants of the child it waited for. Like `Child::wait`, the
+/// standard input of the child is closed first. Fails with `ECHILD` if the child was already
+/// waited for, e.g. with `Child::try_wait`.
+pub fn wait_with_rusage(mut child: Child) -> Result<(ExitStatus, Rusage), Error> {
+    drop(child.stdin.take());
+    let mut status = 0;
+    let mut usage: rusage = unsafe { mem::zeroed() };
+    loop {
+        match unsafe { libc::wait4(child.id() as pid_t, &mut status, 0, &mut usage) } {
+            -1 if errno().0 == EINTR => continue,
+            -1 => return Err(Error::last_os_error()),
+            // Dropping `child` does not wait for it again
+            _ => return Ok((ExitStatus::from_raw(status), Rusage::from_raw(&usage))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::ECHILD;
+    use std::convert::TryFrom;
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    use super::{get_rlimit_for, set_rlimit_for};
+    use super::{adjust_self_priority, get_priority_for, get_rlimit, get_self_priority, getrusage,
+                set_priority_checked, set_priority_for, set_rlimit, wait_with_rusage, Nice,
+                Resource, Rlimit, Rusage, RusageTarget, Which, Who};
+    use error::{Error, ErrorKind};
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    use target::Pid;
+
+    #[test]
+    fn test_set_priority_checked() {
+        thread::spawn(|| {
+            set_priority_checked(Which::Process, 0, 5).unwrap();
+            assert_eq!(5, get_self_priority(Which::Process).unwrap());
+            match set_priority_checked(Which::Process, 0, 100) {
+                Err(Error::Clamped { requested: 100, actual: 19 }) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_nice() {
+        assert_eq!(-20..=19, Nice::range());
+        assert_eq!(Nice::MIN, Nice::new(-20).unwrap());
+        assert_eq!(Nice::MAX, Nice::new(19).unwrap());
+        assert_eq!(Nice::DEFAULT, Nice::default());
+        assert_eq!(-20, i32::from(Nice::MIN));
+        assert_eq!(19, Nice::try_from(19).unwrap().get());
+        for &invalid in &[-21, 20, 100, i32::MIN] {
+            match Nice::new(invalid) {
+                Err(Error::InvalidArgument(_)) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_nice_saturating() {
+        assert_eq!(Nice::MAX, Nice::new(15).unwrap().saturating_add(10));
+        assert_eq!(Nice::MIN, Nice::new(-15).unwrap().saturating_sub(10));
+        assert_eq!(Nice::new(5).unwrap(), Nice::DEFAULT.saturating_add(5));
+        assert_eq!(Nice::MAX, Nice::MAX.saturating_add(i32::MAX));
+        assert_eq!(Nice::MIN, Nice::MIN.saturating_sub(i32::MAX));
+    }
+
+    #[test]
+    fn test_set_priority_nice() {
+        thread::spawn(|| {
+            set_priority_for(Who::Process(0), Nice::new(6).unwrap()).unwrap();
+            assert_eq!(6, get_self_priority(Which::Process).unwrap());
+            set_priority_checked(Which::Process, 0, Nice::MAX).unwrap();
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_who_to_raw() {
+        assert_eq!(Who::User(3_000_000_000), Who::from_which(Which::User, -1_294_967_296));
+        let (_, id) = Who::User(3_000_000_000).to_raw();
+        assert_eq!(3_000_000_000, id);
+        assert_eq!(Who::Group(42), Who::from_which(Which::Group, 42));
+        let (_, id) = Who::Group(42).to_raw();
+        assert_eq!(42, id);
+        assert_eq!(Who::Process(42), Who::from_which(Which::Process, 42));
+    }
+
+    #[test]
+    fn test_priority_for_large_uid() {
+        let error = get_priority_for(Who::User(3_000_000_000)).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+        let error = set_priority_for(Who::User(3_000_000_000), 5).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+        thread::spawn(|| {
+            set_priority_for(Who::Process(0), 3).unwrap();
+            assert_eq!(3, get_priority_for(Who::Process(0)).unwrap());
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_adjust_self_priority() {
+        thread::spawn(|| {
+            let priority = get_self_priority(Which::Process).unwrap();
+            assert_eq!(priority + 2, adjust_self_priority(2).unwrap());
+            assert_eq!(priority + 2, get_self_priority(Which::Process).unwrap());
+            // Lowering the nice value needs `RLIMIT_NICE` or `CAP_SYS_NICE`
+            match adjust_self_priority(-1) {
+                Err(ref error) if error.kind() == ErrorKind::PermissionDenied => {
+                    assert_eq!(priority + 2, get_self_priority(Which::Process).unwrap());
+                }
+                result => assert_eq!(priority + 1, result.unwrap()),
+            }
+            match adjust_self_priority(100) {
+                Err(Error::Clamped { actual: 19, .. }) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+            assert_eq!(19, get_self_priority(Which::Process).unwrap());
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_set_rlimit() {
+        let limit = get_rlimit(Resource::NoFile).unwrap();
+        // Other tests run meanwhile, so leave them almost all file descriptors
+        let lowered = Rlimit { soft: limit.soft.map(|soft| soft - 1), hard: limit.hard };
+        set_rlimit(Resource::NoFile, lowered).unwrap();
+        let result = get_rlimit(Resource::NoFile);
+        set_rlimit(Resource::NoFile, limit).unwrap();
+        assert_eq!(lowered, result.unwrap());
+        assert_eq!(limit, get_rlimit(Resource::NoFile).unwrap());
+
+        let invalid = Rlimit { soft: Some(64), hard: Some(32) };
+        let error = set_rlimit(Resource::NoFile, invalid).unwrap_err();
+        assert_eq!(ErrorKind::InvalidArgument, error.kind());
+    }
+
+    #[test]
+    fn test_rlimit_infinity() {
+        let limit = Rlimit { soft: None, hard: Some(5) };
+        assert_eq!(limit, Rlimit::from_raw(limit.to_raw()));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn test_rlimit_for() {
+        assert_eq!(get_rlimit(Resource::Core).unwrap(),
+                   get_rlimit_for(Pid::this(), Resource::Core).unwrap());
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let pid = Pid::from(&child);
+        let lowered = Rlimit { soft: Some(0), hard: Some(0) };
+        let previous = set_rlimit_for(pid, Resource::Core, lowered);
+        let current = get_rlimit_for(pid, Resource::Core);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(get_rlimit(Resource::Core).unwrap(), previous.unwrap());
+        assert_eq!(lowered, current.unwrap());
+        let error = get_rlimit_for(Pid::from_raw(i32::MAX), Resource::Core).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+
+    #[test]
+    fn test_getrusage() {
+        let process = getrusage(RusageTarget::SelfProcess).unwrap();
+        assert!(process.maxrss > 1024 * 1024);
+        assert!(process.utime + process.stime > Duration::from_secs(0));
+        getrusage(RusageTarget::Children).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_getrusage_thread() {
+        let (before, after) = thread::spawn(|| {
+            let before = getrusage(RusageTarget::Thread).unwrap();
+            let sum = (0..1_000_000u64).fold(0u64, |sum, i| sum.wrapping_add(i * i));
+            assert!(sum > 0);
+            thread::yield_now();
+            (before, getrusage(RusageTarget::Thread).unwrap())
+        }).join().unwrap();
+        let delta = after.delta(&before);
+        assert!(delta.utime + delta.stime > Duration::from_secs(0));
+        assert!(getrusage(RusageTarget::SelfProcess).unwrap().utime >= delta.utime);
+    }
+
+    #[test]
+    fn test_rusage_delta() {
+        let earlier = Rusage {
+            utime: Duration::from_millis(10),
+            stime: Duration::from_millis(4),
+            maxrss: 4096,
+            voluntary_switches: 3,
+            involuntary_switches: 2,
+        };
+        let later = Rusage {
+            utime: Duration::from_millis(25),
+            stime: Duration::from_millis(4),
+            maxrss: 8192,
+            voluntary_switches: 10,
+            involuntary_switches: 1,
+        };
+        let delta = later.delta(&earlier);
+        assert_eq!(Duration::from_millis(15), delta.utime);
+        assert_eq!(Duration::from_millis(0), delta.stime);
+        assert_eq!(8192, delta.maxrss);
+        assert_eq!(7, delta.voluntary_switches);
+        assert_eq!(0, delta.involuntary_switches);
+    }
+
+    #[test]
+    fn test_wait_with_rusage() {
+        let child = Command::new("true").spawn().unwrap();
+        let (status, usage) = wait_with_rusage(child).unwrap();
+        assert!(status.success());
+        assert!(usage.maxrss > 0);
+
+        let child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let (status, _) = wait_with_rusage(child).unwrap();
+        assert_eq!(Some(3), status.code());
+
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        let error = wait_with_rusage(child).unwrap_err();
+        assert_eq!(Some(ECHILD), error.raw_os_error());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        assert_eq!("\"group\"", ::serde_json::to_string(&Which::Group).unwrap());
+        assert_eq!(Which::User, ::serde_json::from_str::<Which>("\"user\"").unwrap());
+        assert!(::serde_json::from_str::<Which>("\"thread\"").is_err());
+        let limit = Rlimit { soft: Some(10), hard: None };
+        let json = ::serde_json::to_string(&limit).unwrap();
+        assert_eq!("{\"soft\":10,\"hard\":null}", json);
+        assert_eq!(limit, ::serde_json::from_str::<Rlimit>(&json).unwrap());
     }
 }
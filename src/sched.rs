@@ -1,14 +1,48 @@
 //! Set and get scheduling policies
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+use libc::EINVAL;
+#[cfg(target_os = "linux")]
+use libc::{cpu_set_t, pthread_getaffinity_np, pthread_setaffinity_np};
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+use libc::{c_int, sched_param, sched_getparam, sched_getscheduler, sched_setscheduler,
+           sched_get_priority_max, sched_get_priority_min, sched_rr_get_interval, timespec,
+           SCHED_FIFO, SCHED_RR, SCHED_BATCH, SCHED_IDLE, SCHED_OTHER};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use libc::{c_uint, pthread_getschedparam, pthread_setschedparam, sched_getcpu, syscall,
+           SYS_getcpu};
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use std::io;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+use std::convert::TryFrom;
+use std::fmt;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::unix::thread::JoinHandleExt;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::thread::JoinHandle;
 #[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
-use libc::{c_int, sched_param, sched_getscheduler, sched_setscheduler, SCHED_FIFO, SCHED_RR,
-           SCHED_BATCH, SCHED_IDLE, SCHED_OTHER};
+use std::time::Duration;
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
-use cpuset::CpuSet;
+use cpuset::{CpuSet, ToCpuSet};
+use error::Error;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+use target::Target;
 
 /// Does not exist in libc yet for some reason. Can be removed when added to libc
 #[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
 const SCHED_DEADLINE: c_int = 6;
 
+/// Flag OR-ed into the policy to reset realtime and negative nice scheduling in children.
+/// Does not exist in libc yet.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+const SCHED_RESET_ON_FORK: c_int = 0x4000_0000;
+
+/// Soft realtime policy of kernels patched with BFS or MuQSS. Reserved but unused by mainline.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+const SCHED_ISO: c_int = 4;
+
 /// Policies that may be set
 ///
 /// Not all of these are supported by this binding.
@@ -16,6 +50,8 @@ const SCHED_DEADLINE: c_int = 6;
 ///
 /// If you are considering another policy, consider updating this source as well.
 #[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Policy {
     Other,
     Fifo,
@@ -23,70 +59,438 @@ pub enum Policy {
     Batch,
     Idle,
     Deadline,
+    /// Soft realtime without privileges, `SCHED_ISO` of kernels patched with BFS or MuQSS.
+    /// Mainline kernels reject it with `EINVAL`. Their `SCHED_IDLEPRIO` is `Idle`.
+    Iso,
+    /// A policy constant passed to the system as is, for policies not known to this crate.
+    /// Never returned by `get_policy`, which returns `Error::UnknownPolicy` for those.
+    Raw(i32),
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+impl Policy {
+    /// Get the range of priorities the kernel accepts for this policy.
+    /// See `get_priority_min` and `get_priority_max`.
+    pub fn priority_range(&self) -> Result<RangeInclusive<i32>, Error> {
+        Ok(get_priority_min(self)?..=get_priority_max(self)?)
+    }
+
+    /// Get the `SCHED_*` constant of this policy, as passed to the system.
+    pub fn to_raw(self) -> i32 {
+        match self {
+            Policy::Other => SCHED_OTHER,
+            Policy::Fifo => SCHED_FIFO,
+            Policy::RoundRobin => SCHED_RR,
+            Policy::Batch => SCHED_BATCH,
+            Policy::Idle => SCHED_IDLE,
+            Policy::Deadline => SCHED_DEADLINE,
+            Policy::Iso => SCHED_ISO,
+            Policy::Raw(policy) => policy,
+        }
+    }
+}
+
+/// Gets the policy of a `SCHED_*` constant, as returned by the system, ignoring the
+/// `SCHED_RESET_ON_FORK` flag. Fails with `Error::UnknownPolicy` holding the constant for
+/// policies this crate does not know, which can still be set with `Policy::Raw`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+impl TryFrom<i32> for Policy {
+    type Error = Error;
+
+    fn try_from(policy: i32) -> Result<Policy, Error> {
+        match policy & !SCHED_RESET_ON_FORK {
+            SCHED_OTHER => Ok(Policy::Other),
+            SCHED_FIFO => Ok(Policy::Fifo),
+            SCHED_RR => Ok(Policy::RoundRobin),
+            SCHED_BATCH => Ok(Policy::Batch),
+            SCHED_IDLE => Ok(Policy::Idle),
+            SCHED_DEADLINE => Ok(Policy::Deadline),
+            SCHED_ISO => Ok(Policy::Iso),
+            _ => Err(Error::UnknownPolicy(policy)),
+        }
+    }
+}
+
+/// Names accepted by `Policy::from_str`, besides their `SCHED_` forms.
+const POLICY_NAMES: &str = "other, normal, fifo, rr, round-robin, batch, idle, deadline, iso, \
+                            or a SCHED_* number";
+
+/// Formats the lowercase name used by `chrt`, e.g. `rr`. `Policy::Raw` formats as its number.
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Policy::Other => f.write_str("other"),
+            Policy::Fifo => f.write_str("fifo"),
+            Policy::RoundRobin => f.write_str("rr"),
+            Policy::Batch => f.write_str("batch"),
+            Policy::Idle => f.write_str("idle"),
+            Policy::Deadline => f.write_str("deadline"),
+            Policy::Iso => f.write_str("iso"),
+            Policy::Raw(policy) => policy.fmt(f),
+        }
+    }
+}
+
+/// Parses the names used by `chrt`, case-insensitively and with or without the `SCHED_` prefix,
+/// e.g. `fifo`, `RR`, `round-robin` or `SCHED_BATCH`. `normal` is an alias of `other`.
+///
+/// A `SCHED_*` number parses as its policy, e.g. `1` as `Policy::Fifo`, and as `Policy::Raw`
+/// for policies this crate does not know.
+impl FromStr for Policy {
+    type Err = ParsePolicyError;
+
+    fn from_str(s: &str) -> Result<Policy, ParsePolicyError> {
+        let name = s.trim().to_ascii_lowercase();
+        let name = name.strip_prefix("sched_").unwrap_or(&name);
+        match name {
+            "other" | "normal" => Ok(Policy::Other),
+            "fifo" => Ok(Policy::Fifo),
+            "rr" | "round-robin" | "round_robin" | "roundrobin" => Ok(Policy::RoundRobin),
+            "batch" => Ok(Policy::Batch),
+            "idle" => Ok(Policy::Idle),
+            "deadline" => Ok(Policy::Deadline),
+            "iso" => Ok(Policy::Iso),
+            _ => match name.parse() {
+                Ok(policy) => Ok(Policy::from_number(policy)),
+                Err(_) => Err(ParsePolicyError { name: s.to_owned() }),
+            },
+        }
+    }
+}
+
+impl Policy {
+    /// Get the policy of a `SCHED_*` number, `Policy::Raw` if this crate does not know it.
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+    fn from_number(policy: i32) -> Policy {
+        match Policy::try_from(policy) {
+            // Keeps flags such as `SCHED_RESET_ON_FORK`, which `try_from` drops
+            Ok(known) if known.to_raw() == policy => known,
+            _ => Policy::Raw(policy),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "emscripten")))]
+    fn from_number(policy: i32) -> Policy {
+        Policy::Raw(policy)
+    }
+}
+
+/// Error returned when parsing a `Policy` from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePolicyError {
+    name: String,
+}
+
+impl fmt::Display for ParsePolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown policy `{}`, expected one of {}", self.name, POLICY_NAMES)
+    }
+}
+
+impl ::std::error::Error for ParsePolicyError {}
+
+/// Serializes as the name formatted by `Display`, e.g. `"fifo"`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Policy {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserializes from any name accepted by `from_str`.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Policy {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Policy, D::Error> {
+        struct PolicyVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for PolicyVisitor {
+            type Value = Policy;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a scheduling policy, one of {}", POLICY_NAMES)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Policy, E> {
+                s.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(PolicyVisitor)
+    }
+}
+
+/// Set the scheduling policy for the calling thread. Same as `set_current_thread_policy`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn set_self_policy(policy: Policy, priority: i32) -> Result<(), Error> {
+    set_current_thread_policy(policy, priority)
 }
 
-/// Set the scheduling policy for this process
+/// Set the scheduling policy for the calling thread only, other threads of the process keep
+/// their policy. See `set_process_policy` to change every thread, and `set_policy`.
 #[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
-pub fn set_self_policy(policy: Policy, priority: i32) -> Result<(), ()> {
-    set_policy(0, policy, priority)
+pub fn set_current_thread_policy(policy: Policy, priority: i32) -> Result<(), Error> {
+    set_policy(Target::CurrentThread, policy, priority)
 }
 
 /// Set the scheduling policy for a process
+///
+/// The priority is checked against `Policy::priority_range` before calling the system: it must be
+/// 0 for `Other`, `Batch` and `Idle`, and usually 1 to 99 for `Fifo` and `RoundRobin`.
+/// `Policy::Deadline` needs parameters and is rejected, use `set_deadline_policy` instead.
+/// Clears the reset on fork flag, see `set_policy_reset_on_fork`.
 #[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
-pub fn set_policy(pid: i32, policy: Policy, priority: i32) -> Result<(), ()> {
-    let c_policy = match policy {
-        Policy::Other => SCHED_OTHER,
-        Policy::Fifo => SCHED_FIFO,
-        Policy::RoundRobin => SCHED_RR,
-        Policy::Batch => SCHED_BATCH,
-        Policy::Idle => SCHED_IDLE,
-        Policy::Deadline => SCHED_DEADLINE,
-    };
+pub fn set_policy<T: Into<Target>>(target: T, policy: Policy, priority: i32) -> Result<(), Error> {
+    validate_priority(policy, priority)?;
+    set_scheduler(target.into().as_raw(), policy.to_raw(), priority)
+}
+
+/// Set the scheduling policy for a process without checking the priority first.
+///
+/// The policy and priority are passed to the system as they are, e.g. for kernels with other
+/// priority ranges. See `set_policy`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn set_policy_unchecked<T: Into<Target>>(target: T, policy: Policy, priority: i32)
+                                              -> Result<(), Error> {
+    set_scheduler(target.into().as_raw(), policy.to_raw(), priority)
+}
+
+/// Set the scheduling policy for this process, with children reset to the default policy.
+/// See `set_policy_reset_on_fork`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn set_self_policy_reset_on_fork(policy: Policy, priority: i32) -> Result<(), Error> {
+    set_policy_reset_on_fork(0, policy, priority)
+}
+
+/// Set the scheduling policy for a process, with children reset to the default policy.
+///
+/// Children created by `fork` do not inherit a `Fifo` or `RoundRobin` policy but get
+/// `Policy::Other` instead, and a negative nice value is reset to 0. The flag is cleared again by
+/// `set_policy`. The priority is checked like in `set_policy`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn set_policy_reset_on_fork<T: Into<Target>>(target: T, policy: Policy, priority: i32)
+                                                  -> Result<(), Error> {
+    validate_priority(policy, priority)?;
+    set_scheduler(target.into().as_raw(), policy.to_raw() | SCHED_RESET_ON_FORK, priority)
+}
+
+/// Check that `priority` can be used with `policy`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub(crate) fn validate_priority(policy: Policy, priority: i32) -> Result<(), Error> {
+    if policy == Policy::Deadline {
+        return Err(Error::InvalidArgument("Policy::Deadline needs parameters, use \
+                                           set_deadline_policy"
+            .into()));
+    }
+    let range = policy.priority_range()?;
+    if !range.contains(&priority) {
+        return Err(Error::InvalidArgument(format!(
+            "priority {} is out of the range {}..={} of {:?}",
+            priority, range.start(), range.end(), policy)));
+    }
+    Ok(())
+}
+
+/// Call `sched_setscheduler` with a policy that can include flags.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+fn set_scheduler(pid: i32, c_policy: c_int, priority: i32) -> Result<(), Error> {
     let params = sched_param { sched_priority: priority };
     let params_ptr: *const sched_param = &params;
 
     match unsafe { sched_setscheduler(pid, c_policy, params_ptr) } {
         0 => Ok(()),
-        _ => Err(()),
+        _ => Err(Error::last_os_error()),
     }
 }
 
 /// Get the scheduling policy for this process
 #[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
-pub fn get_self_policy() -> Result<Policy, ()> {
+pub fn get_self_policy() -> Result<Policy, Error> {
     get_policy(0)
 }
 
 /// Get the scheduling policy for a process
+///
+/// The reset on fork flag is not part of the policy, see `get_reset_on_fork`. A policy unknown
+/// to this crate returns `Error::UnknownPolicy`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn get_policy<T: Into<Target>>(target: T) -> Result<Policy, Error> {
+    Policy::try_from(get_scheduler(target.into().as_raw())?)
+}
+
+/// Check if children of this process are reset to the default policy. See `get_reset_on_fork`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn get_self_reset_on_fork() -> Result<bool, Error> {
+    get_reset_on_fork(0)
+}
+
+/// Check if children of a process are reset to the default policy, as set with
+/// `set_policy_reset_on_fork`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn get_reset_on_fork<T: Into<Target>>(target: T) -> Result<bool, Error> {
+    Ok(get_scheduler(target.into().as_raw())? & SCHED_RESET_ON_FORK != 0)
+}
+
+/// Call `sched_getscheduler`, returning the policy including flags.
 #[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
-pub fn get_policy(pid: i32) -> Result<Policy, ()> {
+fn get_scheduler(pid: i32) -> Result<c_int, Error> {
     match unsafe { sched_getscheduler(pid) } {
-        SCHED_OTHER => Ok(Policy::Other),
-        SCHED_FIFO => Ok(Policy::Fifo),
-        SCHED_RR => Ok(Policy::RoundRobin),
-        SCHED_BATCH => Ok(Policy::Batch),
-        SCHED_IDLE => Ok(Policy::Idle),
-        SCHED_DEADLINE => Ok(Policy::Deadline),
-        -1 => Err(()),
-        policy @ _ => panic!("Policy {} does not exist", policy),
+        -1 => Err(Error::last_os_error()),
+        policy => Ok(policy),
+    }
+}
+
+/// Get the scheduling policy and priority for this process. See `get_policy_and_priority`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn get_self_policy_and_priority() -> Result<(Policy, i32), Error> {
+    get_policy_and_priority(0)
+}
+
+/// Get the scheduling policy for a process along with its priority.
+///
+/// The priority is 0 for policies other than `Fifo` and `RoundRobin`. The result can be passed
+/// back to `set_policy` to restore the scheduling of a process.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn get_policy_and_priority<T: Into<Target>>(target: T) -> Result<(Policy, i32), Error> {
+    let pid = target.into().as_raw();
+    let policy = get_policy(pid)?;
+    let mut params = sched_param { sched_priority: 0 };
+    match unsafe { sched_getparam(pid, &mut params) } {
+        0 => Ok((policy, params.sched_priority)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Set the scheduling policy for a thread started with `std::thread`.
+///
+/// The priority is checked like in `set_policy`. This races with the thread exiting: once it
+/// has exited the error is of kind `ErrorKind::NoSuchProcess`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn set_thread_policy<T>(handle: &JoinHandle<T>, policy: Policy, priority: i32)
+                            -> Result<(), Error> {
+    validate_priority(policy, priority)?;
+    let params = sched_param { sched_priority: priority };
+    match unsafe { pthread_setschedparam(handle.as_pthread_t(), policy.to_raw(), &params) } {
+        0 => Ok(()),
+        code => Err(Error::from_raw_os_error(code)),
+    }
+}
+
+/// Get the scheduling policy for a thread started with `std::thread`.
+///
+/// This races with the thread exiting: once it has exited the error is of kind
+/// `ErrorKind::NoSuchProcess`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn get_thread_policy<T>(handle: &JoinHandle<T>) -> Result<Policy, Error> {
+    let mut policy = 0;
+    let mut params = sched_param { sched_priority: 0 };
+    match unsafe { pthread_getschedparam(handle.as_pthread_t(), &mut policy, &mut params) } {
+        0 => Policy::try_from(policy),
+        code => Err(Error::from_raw_os_error(code)),
+    }
+}
+
+/// Get the round robin time slice for this process. See `rr_interval`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn self_rr_interval() -> Result<Duration, Error> {
+    rr_interval(0)
+}
+
+/// Get the time slice a process scheduled with `Policy::RoundRobin` runs before it is preempted.
+///
+/// Returns `Duration::ZERO` for processes with any other policy, as they are not scheduled in
+/// round robin time slices. If the process does not exist the error is of kind
+/// `ErrorKind::NoSuchProcess`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn rr_interval<T: Into<Target>>(target: T) -> Result<Duration, Error> {
+    let pid = target.into().as_raw();
+    match get_policy(pid)? {
+        Policy::RoundRobin => {}
+        _ => return Ok(Duration::ZERO),
+    }
+    let mut interval = timespec { tv_sec: 0, tv_nsec: 0 };
+    match unsafe { sched_rr_get_interval(pid, &mut interval) } {
+        0 => Ok(Duration::new(interval.tv_sec as u64, interval.tv_nsec as u32)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Get the lowest priority the kernel accepts for `policy`.
+///
+/// This is 1 for `Fifo` and `RoundRobin` on Linux and 0 for the other policies.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn get_priority_min(policy: &Policy) -> Result<i32, Error> {
+    match unsafe { sched_get_priority_min(policy.to_raw()) } {
+        -1 => Err(Error::last_os_error()),
+        priority => Ok(priority),
+    }
+}
+
+/// Get the highest priority the kernel accepts for `policy`.
+///
+/// This is 99 for `Fifo` and `RoundRobin` on Linux and 0 for the other policies.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+pub fn get_priority_max(policy: &Policy) -> Result<i32, Error> {
+    match unsafe { sched_get_priority_max(policy.to_raw()) } {
+        -1 => Err(Error::last_os_error()),
+        priority => Ok(priority),
     }
 }
 
-/// Set the cpu affinity for the current thread See `set_affinity`.
+/// Set the cpu affinity for the calling thread. Same as `set_current_thread_affinity`.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub fn set_self_affinity<'a, C: ToCpuSet<'a>>(cpuset: C) -> Result<(), Error> {
+    set_current_thread_affinity(cpuset)
+}
+
+/// Set the cpu affinity for the calling thread only, other threads of the process keep their
+/// affinity. See `set_process_affinity` to change every thread, and `set_affinity`.
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
-pub fn set_self_affinity(cpuset: CpuSet) -> Result<(), ()> {
-    set_affinity(0, cpuset)
+pub fn set_current_thread_affinity<'a, C: ToCpuSet<'a>>(cpuset: C) -> Result<(), Error> {
+    set_affinity(Target::CurrentThread, cpuset)
 }
 
 /// Set the cpu affinity for a thread.
+///
+/// Accepts a `&CpuSet`, so the same set can be applied to many threads, a `CpuSet` by value, or
+/// anything convertible to one, such as a plain `u64` mask. The set is not modified by the call.
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
-pub fn set_affinity(pid: i32, cpuset: CpuSet) -> Result<(), ()> {
-    cpuset.set_affinity(pid)
+pub fn set_affinity<'a, T, C>(target: T, cpuset: C) -> Result<(), Error>
+    where T: Into<Target>, C: ToCpuSet<'a>
+{
+    cpuset.to_cpuset().set_affinity(target)
+}
+
+/// Set the cpu affinity for a thread and return the affinity the kernel actually applied.
+///
+/// The kernel silently restricts the requested CPUs to those that are online and allowed by
+/// the cgroup cpuset of the thread, so the result can be a subset of `cpuset`.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub fn set_affinity_checked<T: Into<Target>>(target: T, cpuset: &CpuSet) -> Result<CpuSet, Error> {
+    let target = target.into();
+    cpuset.set_affinity(target)?;
+    get_affinity_auto(target)
+}
+
+/// Set the cpu affinity for a thread, failing if the kernel did not apply exactly `cpuset`.
+///
+/// The affinity the kernel applied is left in place and returned in
+/// `Error::AffinityRestricted`. See `set_affinity_checked`.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub fn set_affinity_strict<T: Into<Target>>(target: T, cpuset: &CpuSet) -> Result<(), Error> {
+    let effective = set_affinity_checked(target, cpuset)?;
+    if effective == *cpuset {
+        Ok(())
+    } else {
+        Err(Error::AffinityRestricted {
+            requested: cpuset.clone(),
+            effective,
+        })
+    }
 }
 
 /// Get the cpu affinity for the current thread. See `get_affinity`.
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
-pub fn get_self_affinity(num_cpus: usize) -> Result<CpuSet, ()> {
+pub fn get_self_affinity(num_cpus: usize) -> Result<CpuSet, Error> {
     get_affinity(0, num_cpus)
 }
 
@@ -95,15 +499,142 @@ pub fn get_self_affinity(num_cpus: usize) -> Result<CpuSet, ()> {
 /// Create and return a `CpuSet` that has room for at least `num_cpus` and with those set
 /// according to the current affinity.
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
-pub fn get_affinity(pid: i32, num_cpus: usize) -> Result<CpuSet, ()> {
-    CpuSet::get_affinity(pid, num_cpus)
+pub fn get_affinity<T: Into<Target>>(target: T, num_cpus: usize) -> Result<CpuSet, Error> {
+    CpuSet::get_affinity(target, num_cpus)
+}
+
+/// Get the cpu affinity for the current thread, sized automatically. See `get_affinity_auto`.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub fn get_self_affinity_auto() -> Result<CpuSet, Error> {
+    get_affinity_auto(0)
+}
+
+/// Get the cpu affinity for a thread without knowing the number of CPUs in the system.
+///
+/// Starts with room for every CPU the kernel supports, or 1024 CPUs if that is unknown, and
+/// doubles the size for as long as the kernel rejects it as too small. The returned `CpuSet` is
+/// shrunk to fit the active CPUs.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+pub fn get_affinity_auto<T: Into<Target>>(target: T) -> Result<CpuSet, Error> {
+    let target = target.into();
+    get_affinity_growing(|num_cpus| CpuSet::get_affinity(target, num_cpus))
+}
+
+/// Call `get_affinity` with room for more and more CPUs until the kernel accepts the size.
+/// See `get_affinity_auto`.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+fn get_affinity_growing<F>(mut get_affinity: F) -> Result<CpuSet, Error>
+    where F: FnMut(usize) -> Result<CpuSet, Error>
+{
+    let mut num_cpus = CpuSet::kernel_max().map_or(1024, |max| max + 1);
+    loop {
+        match get_affinity(num_cpus) {
+            Ok(mut cpuset) => {
+                cpuset.shrink_to_fit();
+                return Ok(cpuset);
+            }
+            Err(error) => {
+                if error.raw_os_error() != Some(EINVAL) || num_cpus >= MAX_AFFINITY_CPUS {
+                    return Err(error);
+                }
+                num_cpus *= 2;
+            }
+        }
+    }
+}
+
+/// Set the cpu affinity for a thread started with `std::thread`.
+///
+/// This races with the thread exiting: once it has exited the error is of kind
+/// `ErrorKind::NoSuchProcess`.
+#[cfg(target_os = "linux")]
+pub fn set_thread_affinity<T>(handle: &JoinHandle<T>, cpuset: &CpuSet) -> Result<(), Error> {
+    let mask = cpuset.mask_ptr() as *const cpu_set_t;
+    match unsafe { pthread_setaffinity_np(handle.as_pthread_t(), cpuset.byte_size(), mask) } {
+        0 => Ok(()),
+        code => Err(Error::from_raw_os_error(code)),
+    }
 }
 
+/// Get the cpu affinity for a thread started with `std::thread`, sized like in
+/// `get_affinity_auto`.
+///
+/// This races with the thread exiting: once it has exited the error is of kind
+/// `ErrorKind::NoSuchProcess`.
+#[cfg(target_os = "linux")]
+pub fn get_thread_affinity<T>(handle: &JoinHandle<T>) -> Result<CpuSet, Error> {
+    get_affinity_growing(|num_cpus| {
+        let mut cpuset = CpuSet::new(num_cpus);
+        let mask = cpuset.mut_mask_ptr() as *mut cpu_set_t;
+        match unsafe { pthread_getaffinity_np(handle.as_pthread_t(), cpuset.byte_size(), mask) } {
+            0 => Ok(cpuset),
+            code => Err(Error::from_raw_os_error(code)),
+        }
+    })
+}
+
+/// Get the CPU the calling thread is running on.
+///
+/// The thread can be moved to another CPU right after the call, unless its affinity allows
+/// only one CPU. Returns an error of kind `ErrorKind::Unsupported` on systems other than Linux
+/// and Android.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn current_cpu() -> Result<usize, Error> {
+    match unsafe { sched_getcpu() } {
+        -1 => Err(Error::last_os_error()),
+        cpu => Ok(cpu as usize),
+    }
+}
+
+/// Get the CPU the calling thread is running on, and the NUMA node of that CPU, as
+/// `(cpu, node)`. See `current_cpu`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn current_cpu_and_node() -> Result<(usize, usize), Error> {
+    let mut cpu: c_uint = 0;
+    let mut node: c_uint = 0;
+    match unsafe { syscall(SYS_getcpu, &mut cpu as *mut c_uint, &mut node as *mut c_uint, 0) } {
+        0 => Ok((cpu as usize, node as usize)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Get the CPU the calling thread is running on. Not supported on this system.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn current_cpu() -> Result<usize, Error> {
+    Err(Error::Io(io::Error::new(io::ErrorKind::Unsupported, "sched_getcpu is not supported")))
+}
+
+/// Get the CPU the calling thread is running on and its NUMA node. Not supported on this
+/// system.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn current_cpu_and_node() -> Result<(usize, usize), Error> {
+    Err(Error::Io(io::Error::new(io::ErrorKind::Unsupported, "getcpu is not supported")))
+}
+
+/// Largest number of CPUs `get_affinity_auto` makes room for before giving up.
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+const MAX_AFFINITY_CPUS: usize = 1 << 20;
+
 #[cfg(test)]
 #[cfg(any(target_os = "linux", target_os = "emscripten"))]
 mod tests {
-    use super::{get_self_affinity, set_self_affinity};
+    use super::{get_policy, get_policy_and_priority, get_self_policy_and_priority,
+                set_self_policy, set_self_policy_reset_on_fork, get_self_policy,
+                get_self_reset_on_fork, rr_interval, self_rr_interval, get_priority_max,
+                get_priority_min, get_self_affinity, get_self_affinity_auto, set_self_affinity,
+                set_affinity_checked, set_affinity_strict, set_policy_unchecked, set_affinity,
+                get_affinity, set_thread_policy, get_thread_policy, set_thread_affinity,
+                get_thread_affinity, current_cpu, current_cpu_and_node, Policy};
+    use libc::{geteuid, EINVAL};
     use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+    use std::convert::TryFrom;
+    use std::process::Command;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use target::{Pid, Target, Tid};
+    use guard::with_affinity;
 
     #[test]
     fn test_set_get_self_affinity() {
@@ -122,8 +653,293 @@ mod tests {
         assert_eq!(mask, read_mask);
     }
 
+    #[test]
+    fn test_set_get_self_affinity_u64() {
+        set_self_affinity(1u64).unwrap();
+        let read_mask = get_self_affinity(1).unwrap().as_u64().unwrap();
+        assert_eq!(1, read_mask);
+    }
+
+    #[test]
+    fn test_set_self_affinity_borrowed() {
+        let cpuset = CpuSet::single(0);
+        for _ in 0..2 {
+            set_self_affinity(&cpuset).unwrap();
+        }
+        assert_eq!(cpuset, get_self_affinity(1).unwrap());
+    }
+
+    #[test]
+    fn test_set_affinity_checked() {
+        let effective = set_affinity_checked(0, &CpuSet::single(0)).unwrap();
+        assert_eq!(CpuSet::single(0), effective);
+        set_affinity_strict(0, &CpuSet::single(0)).unwrap();
+    }
+
+    #[test]
+    fn test_set_affinity_checked_restricted() {
+        let requested = CpuSet::from_range(0..1024);
+        let effective = set_affinity_checked(0, &requested).unwrap();
+        assert!(!effective.is_empty());
+        assert!(effective.is_subset(&CpuSet::online().unwrap()));
+        match set_affinity_strict(0, &requested) {
+            Err(Error::AffinityRestricted { requested: r, effective: e }) => {
+                assert_eq!(requested, r);
+                assert_eq!(effective, e);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_get_self_affinity_auto() {
+        set_self_affinity(CpuSet::single(0)).unwrap();
+        let cpuset = get_self_affinity_auto().unwrap();
+        assert_eq!(CpuSet::single(0), cpuset);
+        assert_eq!(8, cpuset.byte_size());
+    }
+
     #[test]
     fn test_set_affinity_no_cpu() {
         assert!(set_self_affinity(CpuSet::new(0)).is_err());
     }
+
+    #[test]
+    fn test_priority_range() {
+        for policy in &[Policy::Fifo, Policy::RoundRobin] {
+            assert_eq!(1, get_priority_min(policy).unwrap());
+            assert_eq!(99, get_priority_max(policy).unwrap());
+            assert_eq!(1..=99, policy.priority_range().unwrap());
+        }
+        for policy in &[Policy::Other, Policy::Batch, Policy::Idle, Policy::Deadline] {
+            assert_eq!(0..=0, policy.priority_range().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_get_policy_and_priority_other() {
+        set_self_policy(Policy::Other, 0).unwrap();
+        assert_eq!((Policy::Other, 0), get_self_policy_and_priority().unwrap());
+        assert!(get_policy_and_priority(i32::MAX).is_err());
+    }
+
+    #[test]
+    fn test_get_policy_and_priority_fifo() {
+        if unsafe { geteuid() } != 0 {
+            return;
+        }
+        match set_self_policy(Policy::Fifo, 10) {
+            Err(ref error) if error.kind() == ErrorKind::PermissionDenied => return,
+            result => result.unwrap(),
+        }
+        let settings = get_self_policy_and_priority();
+        set_self_policy(Policy::Other, 0).unwrap();
+        assert_eq!((Policy::Fifo, 10), settings.unwrap());
+    }
+
+    #[test]
+    fn test_rr_interval() {
+        set_self_policy(Policy::Other, 0).unwrap();
+        assert_eq!(Duration::ZERO, self_rr_interval().unwrap());
+        match set_self_policy(Policy::RoundRobin, 1) {
+            Err(ref error) if error.kind() == ErrorKind::PermissionDenied => {}
+            result => {
+                result.unwrap();
+                let interval = self_rr_interval();
+                set_self_policy(Policy::Other, 0).unwrap();
+                assert!(interval.unwrap() > Duration::ZERO);
+            }
+        }
+        let error = rr_interval(i32::MAX).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+
+    #[test]
+    fn test_reset_on_fork() {
+        // Runs on a thread of its own, which keeps the flag without privileges
+        thread::spawn(|| {
+            set_self_policy_reset_on_fork(Policy::Other, 0).unwrap();
+            assert!(get_self_reset_on_fork().unwrap());
+            assert_eq!(Policy::Other, get_self_policy().unwrap());
+            // Clearing the flag needs `CAP_SYS_NICE`
+            match set_self_policy(Policy::Other, 0) {
+                Err(ref error) if error.kind() == ErrorKind::PermissionDenied => {
+                    assert!(get_self_reset_on_fork().unwrap());
+                }
+                result => {
+                    result.unwrap();
+                    assert!(!get_self_reset_on_fork().unwrap());
+                }
+            }
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_set_policy_deadline() {
+        match set_self_policy(Policy::Deadline, 0) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_set_policy_invalid_priority() {
+        for &(policy, priority) in &[(Policy::Other, 50), (Policy::Batch, -1), (Policy::Fifo, 0),
+                                     (Policy::RoundRobin, 100)] {
+            match set_self_policy(policy, priority) {
+                Err(Error::InvalidArgument(ref message)) => {
+                    assert!(message.contains(&format!("priority {} ", priority)), "{}", message);
+                }
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+        match set_self_policy(Policy::Other, 50) {
+            Err(error) => assert!(error.to_string().contains("0..=0"), "{}", error),
+            Ok(()) => panic!("priority 50 accepted for Policy::Other"),
+        }
+        let error = set_policy_unchecked(0, Policy::Other, 50).unwrap_err();
+        assert_eq!(Some(EINVAL), error.raw_os_error());
+    }
+
+    #[test]
+    fn test_policy_try_from() {
+        assert_eq!(Policy::RoundRobin, Policy::try_from(2).unwrap());
+        assert_eq!(Policy::Fifo, Policy::try_from(0x4000_0001).unwrap());
+        for &policy in &[42, -2, 0x4000_002a] {
+            match Policy::try_from(policy) {
+                Err(Error::UnknownPolicy(unknown)) => assert_eq!(policy, unknown),
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_policy_raw_round_trip() {
+        for &policy in &[Policy::Other, Policy::Fifo, Policy::RoundRobin, Policy::Batch,
+                         Policy::Idle, Policy::Deadline, Policy::Iso] {
+            assert_eq!(policy, Policy::try_from(policy.to_raw()).unwrap());
+        }
+        assert_eq!(6, Policy::Deadline.to_raw());
+    }
+
+    #[test]
+    fn test_policy_iso_raw() {
+        assert_eq!(Policy::Iso, Policy::try_from(4).unwrap());
+        assert_eq!(4, Policy::Iso.to_raw());
+        assert_eq!(42, Policy::Raw(42).to_raw());
+        if Policy::Iso.priority_range().is_err() {
+            let error = set_self_policy(Policy::Iso, 0).unwrap_err();
+            assert_eq!(ErrorKind::InvalidArgument, error.kind());
+        }
+        set_policy_unchecked(0, Policy::Raw(3), 0).unwrap();
+        let policy = get_self_policy();
+        set_self_policy(Policy::Other, 0).unwrap();
+        assert_eq!(Policy::Batch, policy.unwrap());
+    }
+
+    #[test]
+    fn test_targets() {
+        set_affinity(Target::CurrentThread, CpuSet::single(0)).unwrap();
+        assert_eq!(CpuSet::single(0), get_affinity(Tid::current(), 1).unwrap());
+        assert_eq!(Policy::Other, get_policy(Pid::this()).unwrap());
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let policy = get_policy(&child);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(Policy::Other, policy.unwrap());
+    }
+
+    #[test]
+    fn test_set_thread_policy() {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            receiver.recv().unwrap();
+            get_self_policy().unwrap()
+        });
+        set_thread_policy(&handle, Policy::Batch, 0).unwrap();
+        assert_eq!(Policy::Batch, get_thread_policy(&handle).unwrap());
+        sender.send(()).unwrap();
+        assert_eq!(Policy::Batch, handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_set_thread_affinity() {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            receiver.recv().unwrap();
+            get_self_affinity_auto().unwrap()
+        });
+        set_thread_affinity(&handle, &CpuSet::single(0)).unwrap();
+        assert_eq!(CpuSet::single(0), get_thread_affinity(&handle).unwrap());
+        sender.send(()).unwrap();
+        assert_eq!(CpuSet::single(0), handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_set_thread_policy_exited() {
+        let handle = thread::spawn(|| ());
+        while !handle.is_finished() {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(10));
+        let error = set_thread_policy(&handle, Policy::Batch, 0).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_current_cpu() {
+        let (cpu, (cpu_of_node, node)) = with_affinity(&CpuSet::single(0), || {
+            (current_cpu().unwrap(), current_cpu_and_node().unwrap())
+        }).unwrap();
+        assert_eq!(0, cpu);
+        assert_eq!(0, cpu_of_node);
+        assert!(CpuSet::for_node(node).unwrap().is_set(0));
+        assert!(CpuSet::online().unwrap().is_set(current_cpu().unwrap()));
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let error = set_self_affinity(CpuSet::new(0)).unwrap_err();
+        assert_eq!(ErrorKind::InvalidArgument, error.kind());
+        assert_eq!(Some(EINVAL), error.raw_os_error());
+        assert_eq!(ErrorKind::NoSuchProcess, get_policy(i32::MAX).unwrap_err().kind());
+    }
+
+    #[test]
+    fn test_policy_from_str_round_trip() {
+        for &policy in &[Policy::Other, Policy::Fifo, Policy::RoundRobin, Policy::Batch,
+                         Policy::Idle, Policy::Deadline, Policy::Iso, Policy::Raw(42)] {
+            assert_eq!(policy, policy.to_string().parse().unwrap());
+        }
+        assert_eq!("rr", Policy::RoundRobin.to_string());
+        assert_eq!("42", Policy::Raw(42).to_string());
+    }
+
+    #[test]
+    fn test_policy_from_str_aliases() {
+        assert_eq!(Policy::Fifo, "FIFO".parse().unwrap());
+        assert_eq!(Policy::Fifo, "SCHED_FIFO".parse().unwrap());
+        assert_eq!(Policy::RoundRobin, "round-robin".parse().unwrap());
+        assert_eq!(Policy::RoundRobin, "sched_rr".parse().unwrap());
+        assert_eq!(Policy::Other, "normal".parse().unwrap());
+        assert_eq!(Policy::Batch, " batch ".parse().unwrap());
+        let error = "fifoo".parse::<Policy>().unwrap_err();
+        assert_eq!("unknown policy `fifoo`, expected one of other, normal, fifo, rr, round-robin, \
+                    batch, idle, deadline, iso, or a SCHED_* number", error.to_string());
+        assert_eq!(Policy::Fifo, "1".parse().unwrap());
+        assert_eq!(Policy::Deadline, " 6 ".parse().unwrap());
+        assert_eq!(Policy::Raw(0x4000_0001), "1073741825".parse().unwrap());
+        assert!("".parse::<Policy>().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_policy_serde() {
+        assert_eq!("\"rr\"", ::serde_json::to_string(&Policy::RoundRobin).unwrap());
+        assert_eq!(Policy::Fifo, ::serde_json::from_str::<Policy>("\"SCHED_FIFO\"").unwrap());
+        let error = ::serde_json::from_str::<Policy>("\"fifoo\"").unwrap_err();
+        assert!(error.to_string().starts_with("unknown policy `fifoo`, expected one of other"));
+        assert!(::serde_json::from_str::<Policy>("1").is_err());
+    }
 }
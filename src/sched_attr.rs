@@ -0,0 +1,398 @@
+//! Set and get extended scheduling attributes with `sched_setattr` and `sched_getattr`
+use libc::{c_uint, syscall, E2BIG, EINVAL, SYS_sched_getattr, SYS_sched_setattr};
+use std::convert::TryFrom;
+use std::io;
+use std::mem;
+use std::ops::{BitOr, BitOrAssign};
+use std::time::Duration;
+use error::Error;
+use sched::Policy;
+use target::Target;
+
+/// Layout of `struct sched_attr` from `linux/sched/types.h`. Does not exist in libc yet.
+#[repr(C)]
+#[derive(Default)]
+struct RawSchedAttr {
+    size: u32,
+    sched_policy: u32,
+    sched_flags: u64,
+    sched_nice: i32,
+    sched_priority: u32,
+    sched_runtime: u64,
+    sched_deadline: u64,
+    sched_period: u64,
+    sched_util_min: u32,
+    sched_util_max: u32,
+}
+
+/// Keep the current policy and its parameters, to only change the utilization clamp.
+const SCHED_FLAG_KEEP_ALL: u64 = 0x08 | 0x10;
+/// Set the minimum or maximum utilization clamp.
+const SCHED_FLAG_UTIL_CLAMP_MIN: u64 = 0x20;
+const SCHED_FLAG_UTIL_CLAMP_MAX: u64 = 0x40;
+
+/// Largest utilization clamp value, meaning the full capacity of the biggest CPU.
+pub const UCLAMP_MAX: u32 = 1024;
+
+/// Parameters of the `Policy::Deadline` policy.
+///
+/// The process gets `runtime` of CPU time within `deadline` after the start of every `period`.
+/// This requires `runtime <= deadline <= period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeadlineParams {
+    /// CPU time guaranteed every period
+    pub runtime: Duration,
+    /// Time after the start of the period by which the runtime is provided
+    pub deadline: Duration,
+    /// Length of the period
+    pub period: Duration,
+}
+
+impl DeadlineParams {
+    /// Check that `runtime <= deadline <= period`, as required by the kernel.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.runtime > self.deadline || self.deadline > self.period {
+            return Err(Error::InvalidArgument(format!(
+                "deadline parameters must satisfy runtime <= deadline <= period, got {:?}",
+                self)));
+        }
+        Ok(())
+    }
+}
+
+/// Flags of the scheduling attributes, combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SchedFlags(u64);
+
+impl SchedFlags {
+    /// Reset realtime and deadline policies and negative nice values in children
+    pub const RESET_ON_FORK: SchedFlags = SchedFlags(0x01);
+    /// Let a `Policy::Deadline` process reclaim bandwidth left unused by others (GRUB)
+    pub const RECLAIM: SchedFlags = SchedFlags(0x02);
+    /// Send `SIGXCPU` to a `Policy::Deadline` process that overruns its runtime
+    pub const DL_OVERRUN: SchedFlags = SchedFlags(0x04);
+
+    /// Get the set without any flags.
+    pub fn empty() -> SchedFlags {
+        SchedFlags(0)
+    }
+
+    /// Create a set from the raw `sched_flags` value.
+    /// Bits unknown to this crate are kept and passed to the kernel, which rejects unsupported
+    /// flags.
+    pub fn from_bits(bits: u64) -> SchedFlags {
+        SchedFlags(bits)
+    }
+
+    /// Get the raw `sched_flags` value.
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Check if all flags in `other` are set.
+    pub fn contains(&self, other: SchedFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Check if no flag is set.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for SchedFlags {
+    type Output = SchedFlags;
+
+    fn bitor(self, other: SchedFlags) -> SchedFlags {
+        SchedFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for SchedFlags {
+    fn bitor_assign(&mut self, other: SchedFlags) {
+        self.0 |= other.0;
+    }
+}
+
+/// Scheduling attributes of a process, as read with `get_sched_attr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedAttr {
+    /// The scheduling policy
+    pub policy: Policy,
+    /// The static priority, only used by `Policy::Fifo` and `Policy::RoundRobin`
+    pub priority: i32,
+    /// The nice value, only used by `Policy::Other` and `Policy::Batch`
+    pub nice: i32,
+    /// The parameters, only used by `Policy::Deadline`
+    pub deadline: Option<DeadlineParams>,
+    /// The active flags
+    pub flags: SchedFlags,
+}
+
+/// Set the `Policy::Deadline` scheduling policy for this process. See `set_deadline_policy`.
+pub fn set_self_deadline_policy(params: &DeadlineParams) -> Result<(), Error> {
+    set_deadline_policy(0, params)
+}
+
+/// Set the `Policy::Deadline` scheduling policy for a process.
+///
+/// The parameters are checked with `DeadlineParams::validate` before calling the system. The
+/// kernel refuses the policy if it can not guarantee the runtime, e.g. when the process may run
+/// on fewer CPUs than its root domain, and requires `CAP_SYS_NICE`.
+pub fn set_deadline_policy<T: Into<Target>>(target: T, params: &DeadlineParams)
+                                            -> Result<(), Error> {
+    set_deadline_policy_with_flags(target, params, SchedFlags::empty())
+}
+
+/// Set the `Policy::Deadline` scheduling policy for this process with `flags`.
+/// See `set_deadline_policy_with_flags`.
+pub fn set_self_deadline_policy_with_flags(params: &DeadlineParams, flags: SchedFlags)
+                                           -> Result<(), Error> {
+    set_deadline_policy_with_flags(0, params, flags)
+}
+
+/// Set the `Policy::Deadline` scheduling policy for a process with `flags`.
+///
+/// Flags the kernel does not support are rejected with `ErrorKind::InvalidArgument`. See
+/// `set_deadline_policy`.
+pub fn set_deadline_policy_with_flags<T: Into<Target>>(target: T, params: &DeadlineParams,
+                                                       flags: SchedFlags) -> Result<(), Error> {
+    params.validate()?;
+    let mut attr = RawSchedAttr {
+        sched_policy: Policy::Deadline.to_raw() as u32,
+        sched_flags: flags.bits(),
+        sched_runtime: nanos(params.runtime)?,
+        sched_deadline: nanos(params.deadline)?,
+        sched_period: nanos(params.period)?,
+        ..RawSchedAttr::default()
+    };
+    set_attr(target.into().as_raw(), &mut attr)
+}
+
+/// Set the utilization clamp of this process. See `set_uclamp`.
+pub fn set_self_uclamp(min: Option<u32>, max: Option<u32>) -> Result<(), Error> {
+    set_uclamp(0, min, max)
+}
+
+/// Set the utilization clamp of a process, hinting the scheduler at the capacity it needs.
+///
+/// The utilization of the process is considered to be at least `min` and at most `max`, on a
+/// scale from 0 to `UCLAMP_MAX`. `None` leaves the current value in place. The policy of the
+/// process is not changed. Kernels without utilization clamping return an error of kind
+/// `ErrorKind::Unsupported`.
+pub fn set_uclamp<T: Into<Target>>(target: T, min: Option<u32>, max: Option<u32>)
+                                   -> Result<(), Error> {
+    for &value in min.iter().chain(max.iter()) {
+        if value > UCLAMP_MAX {
+            return Err(Error::InvalidArgument(format!(
+                "utilization clamp {} is larger than {}", value, UCLAMP_MAX)));
+        }
+    }
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(Error::InvalidArgument(format!(
+                "utilization clamp minimum {} is larger than maximum {}", min, max)));
+        }
+    }
+    let mut attr = RawSchedAttr {
+        sched_flags: SCHED_FLAG_KEEP_ALL,
+        ..RawSchedAttr::default()
+    };
+    if let Some(min) = min {
+        attr.sched_flags |= SCHED_FLAG_UTIL_CLAMP_MIN;
+        attr.sched_util_min = min;
+    }
+    if let Some(max) = max {
+        attr.sched_flags |= SCHED_FLAG_UTIL_CLAMP_MAX;
+        attr.sched_util_max = max;
+    }
+    set_attr(target.into().as_raw(), &mut attr).map_err(|error| match error.raw_os_error() {
+        // The values are valid, so the kernel does not know the flags or the larger attributes
+        Some(EINVAL) | Some(E2BIG) => {
+            let message = "utilization clamping is not supported by this kernel";
+            Error::Io(io::Error::new(io::ErrorKind::Unsupported, message))
+        }
+        _ => error,
+    })
+}
+
+/// Get the utilization clamp of this process. See `get_uclamp`.
+pub fn get_self_uclamp() -> Result<(u32, u32), Error> {
+    get_uclamp(0)
+}
+
+/// Get the utilization clamp of a process as `(min, max)`. See `set_uclamp`.
+pub fn get_uclamp<T: Into<Target>>(target: T) -> Result<(u32, u32), Error> {
+    let attr = get_attr(target.into().as_raw())?;
+    Ok((attr.sched_util_min, attr.sched_util_max))
+}
+
+/// Get the scheduling attributes of this process. See `get_sched_attr`.
+pub fn get_self_sched_attr() -> Result<SchedAttr, Error> {
+    get_sched_attr(0)
+}
+
+/// Get the scheduling attributes of a process, including the parameters of `Policy::Deadline`.
+/// A policy unknown to this crate returns `Error::UnknownPolicy`.
+pub fn get_sched_attr<T: Into<Target>>(target: T) -> Result<SchedAttr, Error> {
+    let attr = get_attr(target.into().as_raw())?;
+    let policy = Policy::try_from(attr.sched_policy as i32)?;
+    let deadline = match policy {
+        Policy::Deadline => Some(DeadlineParams {
+            runtime: Duration::from_nanos(attr.sched_runtime),
+            deadline: Duration::from_nanos(attr.sched_deadline),
+            period: Duration::from_nanos(attr.sched_period),
+        }),
+        _ => None,
+    };
+    Ok(SchedAttr {
+        policy,
+        priority: attr.sched_priority as i32,
+        nice: attr.sched_nice,
+        deadline,
+        flags: SchedFlags::from_bits(attr.sched_flags),
+    })
+}
+
+/// Convert a `Duration` to the nanoseconds passed to the system.
+fn nanos(duration: Duration) -> Result<u64, Error> {
+    u64::try_from(duration.as_nanos()).map_err(|_| {
+        Error::InvalidArgument(format!("duration of {:?} is too long", duration))
+    })
+}
+
+/// Call `sched_setattr`.
+fn set_attr(pid: i32, attr: &mut RawSchedAttr) -> Result<(), Error> {
+    attr.size = mem::size_of::<RawSchedAttr>() as u32;
+    match unsafe { syscall(SYS_sched_setattr, pid, attr as *mut RawSchedAttr, 0 as c_uint) } {
+        0 => Ok(()),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Call `sched_getattr`.
+fn get_attr(pid: i32) -> Result<RawSchedAttr, Error> {
+    let mut attr = RawSchedAttr::default();
+    let size = mem::size_of::<RawSchedAttr>() as c_uint;
+    match unsafe {
+        syscall(SYS_sched_getattr, pid, &mut attr as *mut RawSchedAttr, size, 0 as c_uint)
+    } {
+        0 => Ok(attr),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_sched_attr, get_self_sched_attr, set_self_deadline_policy,
+                set_self_deadline_policy_with_flags, set_self_uclamp, get_self_uclamp,
+                DeadlineParams, SchedFlags, UCLAMP_MAX};
+    use error::{Error, ErrorKind};
+    use sched::{set_self_policy, Policy};
+    use std::time::Duration;
+
+    fn params(runtime: u64, deadline: u64, period: u64) -> DeadlineParams {
+        DeadlineParams {
+            runtime: Duration::from_millis(runtime),
+            deadline: Duration::from_millis(deadline),
+            period: Duration::from_millis(period),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        params(10, 30, 100).validate().unwrap();
+        params(10, 10, 10).validate().unwrap();
+        for invalid in &[params(30, 10, 100), params(10, 100, 30), params(100, 30, 10)] {
+            match set_self_deadline_policy(invalid) {
+                Err(Error::InvalidArgument(_)) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_sched_attr() {
+        set_self_policy(Policy::Other, 0).unwrap();
+        let attr = get_self_sched_attr().unwrap();
+        assert_eq!(Policy::Other, attr.policy);
+        assert_eq!(0, attr.priority);
+        assert_eq!(None, attr.deadline);
+        let error = get_sched_attr(i32::MAX).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+    }
+
+    #[test]
+    fn test_set_deadline_policy() {
+        let requested = params(10, 30, 100);
+        match set_self_deadline_policy(&requested) {
+            Err(ref error) if error.kind() != ErrorKind::InvalidArgument => return,
+            result => result.unwrap(),
+        }
+        let attr = get_self_sched_attr();
+        set_self_policy(Policy::Other, 0).unwrap();
+        let attr = attr.unwrap();
+        assert_eq!(Policy::Deadline, attr.policy);
+        assert_eq!(Some(requested), attr.deadline);
+        assert!(attr.flags.is_empty());
+    }
+
+    #[test]
+    fn test_sched_flags() {
+        let mut flags = SchedFlags::empty();
+        assert!(flags.is_empty());
+        flags |= SchedFlags::RECLAIM;
+        assert!(flags.contains(SchedFlags::RECLAIM));
+        assert!(!flags.contains(SchedFlags::RECLAIM | SchedFlags::DL_OVERRUN));
+        assert_eq!(0x07, (flags | SchedFlags::DL_OVERRUN | SchedFlags::RESET_ON_FORK).bits());
+        assert_eq!(flags, SchedFlags::from_bits(0x02));
+    }
+
+    #[test]
+    fn test_set_deadline_policy_with_flags() {
+        let requested = params(10, 30, 100);
+        let flags = SchedFlags::DL_OVERRUN | SchedFlags::RESET_ON_FORK;
+        match set_self_deadline_policy_with_flags(&requested, flags) {
+            Err(ref error) if error.kind() != ErrorKind::InvalidArgument => return,
+            result => result.unwrap(),
+        }
+        let attr = get_self_sched_attr();
+        set_self_policy(Policy::Other, 0).unwrap();
+        assert!(attr.unwrap().flags.contains(flags));
+        let unknown = SchedFlags::from_bits(1 << 40);
+        let error = set_self_deadline_policy_with_flags(&requested, unknown).unwrap_err();
+        assert_eq!(ErrorKind::InvalidArgument, error.kind());
+    }
+
+    #[test]
+    fn test_set_uclamp_invalid() {
+        let invalid = [(Some(UCLAMP_MAX + 1), None), (None, Some(2000)), (Some(600), Some(500))];
+        for &(min, max) in &invalid {
+            match set_self_uclamp(min, max) {
+                Err(Error::InvalidArgument(_)) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_uclamp() {
+        match set_self_uclamp(Some(128), Some(512)) {
+            Err(ref error) if error.kind() == ErrorKind::Unsupported => return,
+            result => result.unwrap(),
+        }
+        let uclamp = get_self_uclamp();
+        set_self_uclamp(Some(0), Some(UCLAMP_MAX)).unwrap();
+        assert_eq!((128, 512), uclamp.unwrap());
+        assert_eq!(Policy::Other, get_self_sched_attr().unwrap().policy);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deadline_params_serde() {
+        let params = params(10, 30, 100);
+        let json = ::serde_json::to_string(&params).unwrap();
+        assert_eq!(params, ::serde_json::from_str::<DeadlineParams>(&json).unwrap());
+    }
+}
@@ -0,0 +1,265 @@
+//! CPU sets describing the CPUs of the system, as reported by the kernel in sysfs
+use libc::{sysconf, _SC_NPROCESSORS_ONLN};
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use cpuset::CpuSet;
+use error::Error;
+
+/// Directory where the kernel describes the CPUs of the system.
+pub(crate) const SYSFS_CPU: &str = "/sys/devices/system/cpu";
+
+impl CpuSet {
+    /// Get the CPUs that are currently online.
+    ///
+    /// Read from `/sys/devices/system/cpu/online`, which can be sparse after CPU hotplug. If that
+    /// file is unavailable, CPUs `0..n` are returned where `n` is the number of online CPUs
+    /// reported by `sysconf`.
+    pub fn online() -> Result<CpuSet, Error> {
+        read_cpulist(Path::new(SYSFS_CPU).join("online")).or_else(|error| {
+            match (error, sysconf_online()) {
+                (Error::Io(_), Some(num_cpus)) => Ok(CpuSet::all(num_cpus)),
+                (error, _) => Err(error),
+            }
+        })
+    }
+
+    /// Get the CPUs that could ever be online on this system, including CPUs that can be
+    /// hotplugged later. Read from `/sys/devices/system/cpu/possible`.
+    pub fn possible() -> Result<CpuSet, Error> {
+        read_cpulist(Path::new(SYSFS_CPU).join("possible"))
+    }
+
+    /// Get the CPUs that are physically present in this system, online or not.
+    /// Read from `/sys/devices/system/cpu/present`.
+    pub fn present() -> Result<CpuSet, Error> {
+        read_cpulist(Path::new(SYSFS_CPU).join("present"))
+    }
+
+    /// Get the CPUs isolated from the general scheduler with the `isolcpus` boot parameter.
+    /// Read from `/sys/devices/system/cpu/isolated`, empty if no CPUs are isolated.
+    pub fn isolated() -> Result<CpuSet, Error> {
+        read_optional_cpulist(Path::new(SYSFS_CPU).join("isolated"))
+    }
+
+    /// Get the CPUs running without the scheduler tick, set with the `nohz_full` boot parameter.
+    /// Read from `/sys/devices/system/cpu/nohz_full`, empty if the kernel does not use it.
+    pub fn nohz_full() -> Result<CpuSet, Error> {
+        read_optional_cpulist(Path::new(SYSFS_CPU).join("nohz_full"))
+    }
+
+    /// Get the highest CPU number the kernel was built to support, one less than `NR_CPUS`.
+    /// Read from `/sys/devices/system/cpu/kernel_max`.
+    pub fn kernel_max() -> Result<usize, Error> {
+        read_number(Path::new(SYSFS_CPU).join("kernel_max"))
+    }
+
+    /// Create a new `CpuSet` with room for every CPU the kernel supports, see `kernel_max`.
+    /// No cpu will be active.
+    pub fn sized_for_system() -> Result<CpuSet, Error> {
+        Ok(CpuSet::new(CpuSet::kernel_max()? + 1))
+    }
+
+    /// Read a `CpuSet` from a file in the CPU list format, e.g. `0-3,8` as used in
+    /// `/sys/devices/system/cpu/online` or a cgroup's `cpuset.cpus.effective`.
+    ///
+    /// Trailing whitespace is ignored and an empty file is read as an empty set. Failing to read
+    /// the file returns `Error::Io`, invalid contents return `Error::Parse`.
+    pub fn from_cpulist_file<P: AsRef<Path>>(path: P) -> Result<CpuSet, Error> {
+        read_cpulist(path)
+    }
+
+    /// Write this `CpuSet` in the CPU list format to a file, e.g. a cgroup's `cpuset.cpus`.
+    /// See `from_cpulist_file`.
+    pub fn write_cpulist_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        Ok(fs::write(path, format!("{}\n", self))?)
+    }
+
+    /// Get the online CPUs that are not isolated, where housekeeping work is expected to run.
+    pub fn housekeeping() -> Result<CpuSet, Error> {
+        Ok(CpuSet::online()?.difference(&CpuSet::isolated()?))
+    }
+}
+
+/// Get the number of CPUs that are currently online.
+///
+/// Falls back to 1 if the number can not be determined.
+pub fn num_cpus_online() -> usize {
+    CpuSet::online()
+        .ok()
+        .map(|cpuset| cpuset.iter().count())
+        .or_else(sysconf_online)
+        .unwrap_or(1)
+}
+
+/// Number of online CPUs according to `sysconf`.
+fn sysconf_online() -> Option<usize> {
+    match unsafe { sysconf(_SC_NPROCESSORS_ONLN) } {
+        n if n > 0 => Some(n as usize),
+        _ => None,
+    }
+}
+
+/// Read a file containing a single number.
+pub(crate) fn read_number<T, P>(path: P) -> Result<T, Error>
+    where T: FromStr,
+          T::Err: Display,
+          P: AsRef<Path>
+{
+    let contents = fs::read_to_string(path.as_ref())?;
+    contents.trim().parse().map_err(|error| {
+        let message = format!("invalid number in {}: {}", path.as_ref().display(), error);
+        Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+    })
+}
+
+/// Read a file in the CPU list format, e.g. `0-3,8`.
+pub(crate) fn read_cpulist<P: AsRef<Path>>(path: P) -> Result<CpuSet, Error> {
+    parse_cpulist(File::open(path)?)
+}
+
+/// Read a file in the CPU list format for a kernel feature that might not be in use.
+/// A missing file is read as an empty set.
+fn read_optional_cpulist<P: AsRef<Path>>(path: P) -> Result<CpuSet, Error> {
+    match File::open(path) {
+        Ok(file) => parse_optional_cpulist(file),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(CpuSet::new(0)),
+        Err(error) => Err(Error::Io(error)),
+    }
+}
+
+/// Parse CPU list formatted contents read from `reader`, where the kernel writes `(null)` if the
+/// feature is not in use.
+fn parse_optional_cpulist<R: Read>(mut reader: R) -> Result<CpuSet, Error> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    match contents.trim() {
+        "(null)" => Ok(CpuSet::new(0)),
+        contents => Ok(contents.parse()?),
+    }
+}
+
+/// Parse CPU list formatted contents read from `reader`.
+fn parse_cpulist<R: Read>(mut reader: R) -> Result<CpuSet, Error> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    Ok(contents.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::{self, Read};
+    use std::path::PathBuf;
+    use super::{num_cpus_online, parse_cpulist, parse_optional_cpulist};
+    use cpuset::CpuSet;
+    use error::Error;
+
+    #[test]
+    fn test_parse_cpulist() {
+        assert_eq!(CpuSet::from_range(0..4), parse_cpulist(&b"0-3\n"[..]).unwrap());
+        let sparse = parse_cpulist(&b"0-3,5-7\n"[..]).unwrap();
+        assert_eq!(vec![0, 1, 2, 3, 5, 6, 7], sparse.to_vec());
+        match parse_cpulist(&b"0-3,x\n"[..]) {
+            Err(Error::Parse(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_cpulist_io_error() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "restricted"))
+            }
+        }
+        match parse_cpulist(Failing) {
+            Err(Error::Io(ref error)) if error.kind() == io::ErrorKind::PermissionDenied => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_optional_cpulist() {
+        assert!(parse_optional_cpulist(&b"\n"[..]).unwrap().is_empty());
+        assert!(parse_optional_cpulist(&b"(null)\n"[..]).unwrap().is_empty());
+        let isolated = parse_optional_cpulist(&b"2-3,6\n"[..]).unwrap();
+        assert_eq!(vec![2, 3, 6], isolated.to_vec());
+        assert!(parse_optional_cpulist(&b"2-\n"[..]).is_err());
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("scheduler-test-{}-{}", name, ::std::process::id()))
+    }
+
+    #[test]
+    fn test_from_cpulist_file() {
+        let path = temp_path("cpulist");
+        for &(contents, cpus) in &[("0-3,8-9,12\n", &[0, 1, 2, 3, 8, 9, 12][..]),
+                                   ("5", &[5][..]),
+                                   ("\n", &[][..]),
+                                   ("", &[][..])] {
+            fs::write(&path, contents).unwrap();
+            assert_eq!(cpus, &CpuSet::from_cpulist_file(&path).unwrap().to_vec()[..]);
+        }
+        fs::write(&path, "0-3,x\n").unwrap();
+        match CpuSet::from_cpulist_file(&path) {
+            Err(Error::Parse(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        fs::remove_file(&path).unwrap();
+        match CpuSet::from_cpulist_file(&path) {
+            Err(Error::Io(ref error)) if error.kind() == io::ErrorKind::NotFound => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_write_cpulist_file() {
+        let path = temp_path("write-cpulist");
+        let cpuset: CpuSet = (0..4).chain(8..10).collect();
+        cpuset.write_cpulist_file(&path).unwrap();
+        assert_eq!("0-3,8-9\n", fs::read_to_string(&path).unwrap());
+        assert_eq!(cpuset, CpuSet::from_cpulist_file(&path).unwrap());
+        CpuSet::new(0).write_cpulist_file(&path).unwrap();
+        assert!(CpuSet::from_cpulist_file(&path).unwrap().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_housekeeping() {
+        let housekeeping = CpuSet::housekeeping().unwrap();
+        assert!(housekeeping.is_subset(&CpuSet::online().unwrap()));
+        assert_eq!(housekeeping, housekeeping.difference(&CpuSet::isolated().unwrap()));
+        CpuSet::nohz_full().unwrap();
+    }
+
+    #[test]
+    fn test_possible_present_online() {
+        let possible = CpuSet::possible().unwrap();
+        let present = CpuSet::present().unwrap();
+        let online = CpuSet::online().unwrap();
+        assert!(present.is_subset(&possible));
+        assert!(online.is_subset(&present));
+    }
+
+    #[test]
+    fn test_sized_for_system() {
+        let cpuset = CpuSet::sized_for_system().unwrap();
+        assert!(cpuset.is_empty());
+        assert_eq!((CpuSet::kernel_max().unwrap() + 1).div_ceil(64) * 64, cpuset.capacity());
+        assert!(cpuset.capacity() >= num_cpus_online());
+        assert!(cpuset.capacity() > CpuSet::possible().unwrap().last_set().unwrap());
+    }
+
+    #[test]
+    fn test_online() {
+        let online = CpuSet::online().unwrap();
+        assert!(!online.is_empty());
+        assert_eq!(online.iter().count(), num_cpus_online());
+    }
+}
@@ -0,0 +1,151 @@
+//! Typed identifiers of the processes and threads that scheduling calls act on
+use libc::{getpid, pid_t, syscall, SYS_gettid};
+use std::process::Child;
+
+/// Identifier of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pid(pid_t);
+
+impl Pid {
+    /// Create a `Pid` from a raw process id.
+    pub fn from_raw(pid: i32) -> Pid {
+        Pid(pid)
+    }
+
+    /// Get the raw process id.
+    pub fn as_raw(self) -> i32 {
+        self.0
+    }
+
+    /// Get the `Pid` of the calling process.
+    pub fn this() -> Pid {
+        Pid(unsafe { getpid() })
+    }
+}
+
+impl From<u32> for Pid {
+    fn from(pid: u32) -> Pid {
+        Pid(pid as pid_t)
+    }
+}
+
+impl<'a> From<&'a Child> for Pid {
+    fn from(child: &'a Child) -> Pid {
+        Pid::from(child.id())
+    }
+}
+
+/// Identifier of a thread, as known to the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tid(pid_t);
+
+impl Tid {
+    /// Create a `Tid` from a raw thread id.
+    pub fn from_raw(tid: i32) -> Tid {
+        Tid(tid)
+    }
+
+    /// Get the raw thread id.
+    pub fn as_raw(self) -> i32 {
+        self.0
+    }
+
+    /// Get the `Tid` of the calling thread.
+    pub fn current() -> Tid {
+        Tid(unsafe { syscall(SYS_gettid) } as pid_t)
+    }
+}
+
+impl From<u32> for Tid {
+    fn from(tid: u32) -> Tid {
+        Tid(tid as pid_t)
+    }
+}
+
+/// The thread a scheduling call acts on.
+///
+/// On Linux the policy, priority and affinity belong to a thread, not a process. Targeting a
+/// process only changes its main thread, threads it already started keep their settings.
+///
+/// Raw ids are accepted for compatibility, where 0 is the calling thread. A raw id does not
+/// tell a process from a thread, prefer `Pid`, `Tid` and `Target::CurrentThread`. Rust can not
+/// deprecate a conversion, so passing a raw id compiles without a warning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// The calling thread
+    CurrentThread,
+    /// The main thread of a process
+    Process(Pid),
+    /// A thread of any process
+    Thread(Tid),
+}
+
+impl Target {
+    /// Get the raw id passed to the system.
+    pub fn as_raw(self) -> i32 {
+        match self {
+            Target::CurrentThread => 0,
+            Target::Process(pid) => pid.as_raw(),
+            Target::Thread(tid) => tid.as_raw(),
+        }
+    }
+}
+
+/// Treats the id as a process, see `Target`.
+impl From<i32> for Target {
+    fn from(pid: i32) -> Target {
+        match pid {
+            0 => Target::CurrentThread,
+            pid => Target::Process(Pid(pid)),
+        }
+    }
+}
+
+impl From<Pid> for Target {
+    fn from(pid: Pid) -> Target {
+        Target::Process(pid)
+    }
+}
+
+impl From<Tid> for Target {
+    fn from(tid: Tid) -> Target {
+        Target::Thread(tid)
+    }
+}
+
+impl<'a> From<&'a Child> for Target {
+    fn from(child: &'a Child) -> Target {
+        Target::Process(Pid::from(child))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::thread;
+    use super::{Pid, Target, Tid};
+
+    #[test]
+    fn test_target_as_raw() {
+        assert_eq!(0, Target::from(0).as_raw());
+        assert_eq!(Target::CurrentThread, Target::from(0));
+        assert_eq!(Target::Process(Pid::from_raw(42)), Target::from(42));
+        assert_eq!(7, Target::from(Tid::from(7u32)).as_raw());
+    }
+
+    #[test]
+    fn test_pid_tid() {
+        assert!(Pid::this().as_raw() > 0);
+        let main = Tid::current();
+        let other = thread::spawn(Tid::current).join().unwrap();
+        assert_ne!(main, other);
+    }
+
+    #[test]
+    fn test_pid_from_child() {
+        let mut child = Command::new("true").spawn().unwrap();
+        assert_eq!(child.id() as i32, Pid::from(&child).as_raw());
+        assert_eq!(Target::Process(Pid::from(&child)), Target::from(&child));
+        child.wait().unwrap();
+    }
+}
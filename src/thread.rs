@@ -0,0 +1,152 @@
+//! Spawn threads with their scheduling set up before they run
+use std::io;
+use std::panic;
+use std::sync::mpsc;
+use std::thread::{Builder, JoinHandle};
+use cpuset::CpuSet;
+use error::Error;
+use resource::{set_priority, Which};
+use sched::{set_current_thread_affinity, set_current_thread_policy, Policy};
+use target::Tid;
+
+/// Scheduling settings applied to a new thread before it runs, see `ThreadConfig::spawn`.
+#[derive(Debug, Default)]
+pub struct ThreadConfig {
+    affinity: Option<CpuSet>,
+    policy: Option<(Policy, i32)>,
+    nice: Option<i32>,
+    builder: Option<Builder>,
+}
+
+impl ThreadConfig {
+    /// Create a `ThreadConfig` where the new thread inherits the settings of the spawning thread.
+    pub fn new() -> ThreadConfig {
+        ThreadConfig::default()
+    }
+
+    /// Set the cpu affinity of the new thread.
+    pub fn affinity(mut self, cpuset: CpuSet) -> ThreadConfig {
+        self.affinity = Some(cpuset);
+        self
+    }
+
+    /// Set the scheduling policy and priority of the new thread, checked like in `set_policy`.
+    pub fn policy(mut self, policy: Policy, priority: i32) -> ThreadConfig {
+        self.policy = Some((policy, priority));
+        self
+    }
+
+    /// Set the nice value of the new thread, used by `Policy::Other` and `Policy::Batch`.
+    pub fn nice(mut self, nice: i32) -> ThreadConfig {
+        self.nice = Some(nice);
+        self
+    }
+
+    /// Spawn the thread with `builder`, e.g. to set its name or stack size.
+    pub fn builder(mut self, builder: Builder) -> ThreadConfig {
+        self.builder = Some(builder);
+        self
+    }
+
+    /// Spawn a thread running `f` with these settings.
+    ///
+    /// The settings are applied by the new thread itself, in the order affinity, policy, nice,
+    /// and this waits until they are. If any of them fails, `f` is not run, the thread exits and
+    /// the error is returned, keeping the raw OS error code.
+    pub fn spawn<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static
+    {
+        let builder = self.builder.unwrap_or_else(Builder::new);
+        let (affinity, policy, nice) = (self.affinity, self.policy, self.nice);
+        let (sender, receiver) = mpsc::channel();
+        let handle = builder.spawn(move || {
+            let setup = apply(affinity.as_ref(), policy, nice);
+            let failed = setup.is_err();
+            let _ = sender.send(setup);
+            if failed {
+                // Exits the thread without running `f` and without reporting a panic
+                panic::resume_unwind(Box::new("thread setup failed"));
+            }
+            f()
+        })?;
+        match receiver.recv() {
+            Ok(Ok(())) => Ok(handle),
+            Ok(Err(error)) => Err(error.into()),
+            Err(_) => Err(io::Error::other("thread exited before its setup")),
+        }
+    }
+}
+
+/// Apply the settings of a `ThreadConfig` to the calling thread.
+fn apply(affinity: Option<&CpuSet>, policy: Option<(Policy, i32)>, nice: Option<i32>)
+         -> Result<(), Error> {
+    if let Some(cpuset) = affinity {
+        set_current_thread_affinity(cpuset)?;
+    }
+    if let Some((policy, priority)) = policy {
+        set_current_thread_policy(policy, priority)?;
+    }
+    if let Some(nice) = nice {
+        set_priority(Which::Process, Tid::current().as_raw(), nice)?;
+    }
+    Ok(())
+}
+
+/// Spawn a thread running `f` with its cpu affinity set to `cpuset`.
+/// See `ThreadConfig::spawn`.
+pub fn spawn_pinned<F, T>(cpuset: CpuSet, f: F) -> io::Result<JoinHandle<T>>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    ThreadConfig::new().affinity(cpuset).spawn(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::EINVAL;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, Builder};
+    use super::{spawn_pinned, ThreadConfig};
+    use cpuset::CpuSet;
+    use resource::{get_self_priority, Which};
+    use sched::{get_self_affinity_auto, get_self_policy, Policy};
+
+    #[test]
+    fn test_spawn_pinned() {
+        let handle = spawn_pinned(CpuSet::single(0), get_self_affinity_auto).unwrap();
+        assert_eq!(CpuSet::single(0), handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_thread_config() {
+        let handle = ThreadConfig::new()
+            .affinity(CpuSet::single(0))
+            .policy(Policy::Batch, 0)
+            .nice(5)
+            .builder(Builder::new().name("configured".into()))
+            .spawn(|| {
+                (thread::current().name().map(String::from),
+                 get_self_policy().unwrap(),
+                 get_self_priority(Which::Process).unwrap())
+            })
+            .unwrap();
+        let (name, policy, nice) = handle.join().unwrap();
+        assert_eq!(Some("configured".into()), name);
+        assert_eq!(Policy::Batch, policy);
+        assert_eq!(5, nice);
+    }
+
+    #[test]
+    fn test_thread_config_failure() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let thread_ran = ran.clone();
+        let error = spawn_pinned(CpuSet::new(0), move || thread_ran.store(true, Ordering::SeqCst))
+            .unwrap_err();
+        assert_eq!(Some(EINVAL), error.raw_os_error());
+        let error = ThreadConfig::new().policy(Policy::Other, 10).spawn(|| ()).unwrap_err();
+        assert_eq!(::std::io::ErrorKind::InvalidInput, error.kind());
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+}
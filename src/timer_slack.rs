@@ -0,0 +1,138 @@
+//! Timer slack, how much later than requested the kernel may expire the timers of a thread
+//!
+//! Coalescing timers saves power at the cost of latency. A slack of 0 has no meaning of its own,
+//! the kernel uses it to reset a thread to its default slack, see `reset_timer_slack`.
+use libc::{c_ulong, prctl, PR_GET_TIMERSLACK, PR_SET_TIMERSLACK};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use error::Error;
+use procfs::proc_dir;
+use target::{Target, Tid};
+
+/// Set the timer slack of the calling thread, at least 1 nanosecond.
+///
+/// Returns an error if `slack` is zero, use `reset_timer_slack` instead.
+pub fn set_timer_slack(slack: Duration) -> Result<(), Error> {
+    set_slack(to_nanos(slack)?)
+}
+
+/// Reset the timer slack of the calling thread to its default, the slack of the thread that
+/// started it.
+pub fn reset_timer_slack() -> Result<(), Error> {
+    set_slack(0)
+}
+
+fn set_slack(nanos: u64) -> Result<(), Error> {
+    match unsafe { prctl(PR_SET_TIMERSLACK, nanos as c_ulong, 0, 0, 0) } {
+        0 => Ok(()),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Get the timer slack of the calling thread.
+pub fn get_timer_slack() -> Result<Duration, Error> {
+    match unsafe { prctl(PR_GET_TIMERSLACK, 0, 0, 0, 0) } {
+        -1 => Err(Error::last_os_error()),
+        nanos => Ok(Duration::from_nanos(nanos as u32 as u64)),
+    }
+}
+
+/// Set the timer slack of a thread, at least 1 nanosecond, by writing
+/// `/proc/<pid>/timerslack_ns`.
+///
+/// Setting the slack of another thread requires `CAP_SYS_NICE`, otherwise this fails with
+/// `ErrorKind::PermissionDenied`. Returns an error if `slack` is zero, use
+/// `reset_timer_slack_for` instead.
+pub fn set_timer_slack_for<T: Into<Target>>(target: T, slack: Duration) -> Result<(), Error> {
+    let nanos = to_nanos(slack)?;
+    Ok(fs::write(slack_file(target.into()), nanos.to_string())?)
+}
+
+/// Reset the timer slack of a thread to its default, the slack it started with. See
+/// `set_timer_slack_for`.
+pub fn reset_timer_slack_for<T: Into<Target>>(target: T) -> Result<(), Error> {
+    Ok(fs::write(slack_file(target.into()), "0")?)
+}
+
+/// Get the timer slack of a thread, read from `/proc/<pid>/timerslack_ns`.
+pub fn get_timer_slack_for<T: Into<Target>>(target: T) -> Result<Duration, Error> {
+    let slack = fs::read_to_string(slack_file(target.into()))?;
+    match slack.trim().parse() {
+        Ok(nanos) => Ok(Duration::from_nanos(nanos)),
+        Err(_) => {
+            let message = format!("invalid timer slack {:?}", slack);
+            Err(Error::Io(io::Error::new(io::ErrorKind::InvalidData, message)))
+        }
+    }
+}
+
+/// File with the timer slack of a thread. It is missing in `/proc/thread-self`, but
+/// `/proc/<tid>` has it for any thread.
+fn slack_file(target: Target) -> PathBuf {
+    let target = match target {
+        Target::CurrentThread => Target::Thread(Tid::current()),
+        target => target,
+    };
+    proc_dir(target).join("timerslack_ns")
+}
+
+/// Convert a timer slack to nanoseconds, rejecting the 0 that means the default slack.
+fn to_nanos(slack: Duration) -> Result<u64, Error> {
+    match slack.as_nanos() {
+        0 => Err(Error::InvalidArgument("a timer slack of 0 resets it to the default".into())),
+        nanos if nanos > c_ulong::MAX as u128 => {
+            Err(Error::InvalidArgument(format!("timer slack {:?} is too large", slack)))
+        }
+        nanos => Ok(nanos as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use super::{get_timer_slack, get_timer_slack_for, reset_timer_slack, reset_timer_slack_for,
+                set_timer_slack, set_timer_slack_for};
+    use error::Error;
+    use target::{Target, Tid};
+
+    #[test]
+    fn test_set_timer_slack() {
+        let default = get_timer_slack().unwrap();
+        set_timer_slack(Duration::from_millis(50)).unwrap();
+        assert_eq!(Duration::from_millis(50), get_timer_slack().unwrap());
+        assert_eq!(Duration::from_millis(50), get_timer_slack_for(Target::CurrentThread).unwrap());
+        set_timer_slack(Duration::from_nanos(1)).unwrap();
+        assert_eq!(Duration::from_nanos(1), get_timer_slack().unwrap());
+        reset_timer_slack().unwrap();
+        assert_eq!(default, get_timer_slack().unwrap());
+    }
+
+    #[test]
+    fn test_set_timer_slack_zero() {
+        match set_timer_slack(Duration::from_secs(0)) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        match set_timer_slack_for(Target::CurrentThread, Duration::from_secs(0)) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_set_timer_slack_for() {
+        let (slack, default) = thread::spawn(|| {
+            let default = get_timer_slack().unwrap();
+            set_timer_slack_for(Tid::current(), Duration::from_micros(200)).unwrap();
+            let slack = get_timer_slack().unwrap();
+            reset_timer_slack_for(Tid::current()).unwrap();
+            assert_eq!(default, get_timer_slack().unwrap());
+            (slack, default)
+        }).join().unwrap();
+        assert_eq!(Duration::from_micros(200), slack);
+        assert!(default > Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,129 @@
+//! CPU topology, cores and their SMT siblings, as reported by the kernel in sysfs
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use cpuset::CpuSet;
+use error::Error;
+use system::{read_cpulist, read_number, SYSFS_CPU};
+
+impl CpuSet {
+    /// Get the online CPUs with one CPU per physical core, the lowest online SMT sibling of
+    /// each core is picked. With SMT disabled this is all online CPUs.
+    pub fn one_cpu_per_core() -> Result<CpuSet, Error> {
+        one_per_core(&CpuSet::online()?, |cpu| match CpuSet::smt_siblings_of(cpu) {
+            // Topology of an online CPU can be hidden, e.g. in restricted containers.
+            Err(Error::Io(ref error)) if error.kind() == io::ErrorKind::NotFound => {
+                Ok(CpuSet::single(cpu))
+            }
+            result => result,
+        })
+    }
+
+    /// Get the SMT siblings of `cpu`, the CPUs sharing a physical core with it, including
+    /// `cpu` itself. Read from `/sys/devices/system/cpu/cpu<cpu>/topology/thread_siblings_list`.
+    pub fn smt_siblings_of(cpu: usize) -> Result<CpuSet, Error> {
+        read_cpulist(topology_path(cpu).join("thread_siblings_list"))
+    }
+}
+
+/// Get the online CPUs grouped by physical package (socket), ordered by package id.
+///
+/// The package of each CPU is read from
+/// `/sys/devices/system/cpu/cpu<N>/topology/physical_package_id`.
+pub fn cpus_by_package() -> Result<Vec<CpuSet>, Error> {
+    group_by_package(&CpuSet::online()?, |cpu| {
+        read_number(topology_path(cpu).join("physical_package_id"))
+    })
+}
+
+/// Group `cpus` by the package id given by `package_of`, ordered by package id.
+fn group_by_package<F>(cpus: &CpuSet, package_of: F) -> Result<Vec<CpuSet>, Error>
+    where F: Fn(usize) -> Result<i32, Error>
+{
+    let mut packages = BTreeMap::new();
+    for cpu in cpus {
+        packages.entry(package_of(cpu)?).or_insert_with(|| CpuSet::new(0)).set(cpu);
+    }
+    Ok(packages.into_values().collect())
+}
+
+/// Path of the topology directory of `cpu`.
+fn topology_path(cpu: usize) -> PathBuf {
+    Path::new(SYSFS_CPU).join(format!("cpu{}", cpu)).join("topology")
+}
+
+/// Pick the lowest CPU of each core among `cpus`, where `siblings_of` gives the siblings of a CPU.
+fn one_per_core<F>(cpus: &CpuSet, siblings_of: F) -> Result<CpuSet, Error>
+    where F: Fn(usize) -> Result<CpuSet, Error>
+{
+    let mut picked = CpuSet::new(0);
+    let mut covered = CpuSet::new(0);
+    for cpu in cpus {
+        if !covered.is_set(cpu) {
+            picked.set(cpu);
+            covered |= &siblings_of(cpu)?;
+            covered.set(cpu);
+        }
+    }
+    Ok(picked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cpus_by_package, group_by_package, one_per_core};
+    use cpuset::CpuSet;
+    use error::Error;
+
+    /// Siblings of a 2 core machine with SMT, where CPU n and n + 2 share a core.
+    fn smt_siblings(cpu: usize) -> Result<CpuSet, Error> {
+        Ok(CpuSet::from_slice(&[cpu % 2, cpu % 2 + 2]))
+    }
+
+    #[test]
+    fn test_one_per_core_smt() {
+        let picked = one_per_core(&CpuSet::from_range(0..4), smt_siblings).unwrap();
+        assert_eq!(vec![0, 1], picked.to_vec());
+    }
+
+    #[test]
+    fn test_one_per_core_offline_sibling() {
+        let online = CpuSet::from_slice(&[1, 2, 3]);
+        let picked = one_per_core(&online, smt_siblings).unwrap();
+        assert_eq!(vec![1, 2], picked.to_vec());
+    }
+
+    #[test]
+    fn test_one_per_core_no_smt() {
+        let picked = one_per_core(&CpuSet::from_range(0..4), |cpu| Ok(CpuSet::single(cpu)));
+        assert_eq!(vec![0, 1, 2, 3], picked.unwrap().to_vec());
+    }
+
+    #[test]
+    fn test_group_by_package() {
+        let cpus = CpuSet::from_slice(&[0, 1, 2, 3, 5, 8]);
+        let packages = group_by_package(&cpus, |cpu| Ok(if cpu % 2 == 0 { 1 } else { 0 })).unwrap();
+        assert_eq!(vec![CpuSet::from_slice(&[1, 3, 5]), CpuSet::from_slice(&[0, 2, 8])],
+                   packages);
+        let packages = group_by_package(&cpus, |_| Ok(0)).unwrap();
+        assert_eq!(vec![cpus], packages);
+    }
+
+    #[test]
+    fn test_cpus_by_package() {
+        let packages = cpus_by_package().unwrap();
+        assert!(!packages.is_empty());
+        let all = packages.iter().fold(CpuSet::new(0), |all, cpus| &all | cpus);
+        assert_eq!(CpuSet::online().unwrap(), all);
+    }
+
+    #[test]
+    fn test_one_cpu_per_core() {
+        let online = CpuSet::online().unwrap();
+        let picked = CpuSet::one_cpu_per_core().unwrap();
+        assert!(!picked.is_empty());
+        assert!(picked.is_subset(&online));
+        for cpu in &picked {
+            assert!(CpuSet::smt_siblings_of(cpu).unwrap().is_set(cpu));
+        }
+    }
+}
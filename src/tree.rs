@@ -0,0 +1,255 @@
+//! Scheduling of a process and all of its descendants
+use libc::ESRCH;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use config::SchedulerConfig;
+use error::{Error, ErrorKind};
+use process::{apply_all_threads, threads_of};
+use resource::{set_priority_for, Who};
+use sched::{set_affinity, set_policy, validate_priority};
+use target::{Pid, Tid};
+
+/// Deepest level of descendants `apply_to_tree` walks, the root is at depth 0.
+const MAX_TREE_DEPTH: usize = 1024;
+
+/// Outcome per process of `apply_to_tree`.
+#[derive(Debug, Default)]
+pub struct TreeReport {
+    /// Processes the settings were applied to, on every thread
+    pub applied: Vec<Pid>,
+    /// Processes where applying a setting failed, with the first error
+    pub failed: Vec<(Pid, Error)>,
+    /// Processes that were found but exited before the settings were applied
+    pub vanished: Vec<Pid>,
+}
+
+/// Apply settings to every thread of a process and of all of its descendants, like renicing a
+/// build and every compiler it started.
+///
+/// Each process gets the settings before its children are listed, so processes started
+/// meanwhile inherit them, except from a parent that was listed already. The children are
+/// read from `/proc/<pid>/task/<tid>/children`, or found by scanning the `PPid` of every
+/// process in `/proc` on kernels without that file. The settings are applied in the order of
+/// `ConfigStep` to each thread like in `set_affinity_all_threads`, without rolling back.
+///
+/// Processes that exit meanwhile are reported as vanished. Every process is visited once, and
+/// descendants deeper than 1024 levels are left out, so a reused pid can not make the walk loop.
+/// Returns an error without changing anything if the priority is invalid for the policy or the
+/// root process does not exist.
+pub fn apply_to_tree(root: Pid, config: &SchedulerConfig) -> Result<TreeReport, Error> {
+    if let Some((policy, priority)) = config.get_policy() {
+        validate_priority(policy, priority)?;
+    }
+    if !Path::new(&format!("/proc/{}", root.as_raw())).exists() {
+        return Err(Error::from_raw_os_error(ESRCH));
+    }
+    let apply = |pid| apply_to_process(pid, config);
+    let children_file = format!("/proc/{0}/task/{0}/children", root.as_raw());
+    if Path::new(&children_file).exists() {
+        Ok(walk_tree(root, children_of, apply))
+    } else {
+        let parents = scan_parents()?;
+        let children = |pid| parents.get(&pid).cloned().unwrap_or_default();
+        Ok(walk_tree(root, children, apply))
+    }
+}
+
+/// Visit `root` and its descendants breadth first, calling `apply` for each before listing
+/// its children with `children`.
+fn walk_tree<C, F>(root: Pid, mut children: C, mut apply: F) -> TreeReport
+    where C: FnMut(Pid) -> Vec<Pid>,
+          F: FnMut(Pid) -> Result<(), Error>
+{
+    let mut report = TreeReport::default();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(root);
+    queue.push_back((root, 0));
+    while let Some((pid, depth)) = queue.pop_front() {
+        match apply(pid) {
+            Ok(()) => report.applied.push(pid),
+            Err(ref error) if error.kind() == ErrorKind::NoSuchProcess => {
+                // An exited process has no children left to list
+                report.vanished.push(pid);
+                continue;
+            }
+            Err(error) => report.failed.push((pid, error)),
+        }
+        if depth == MAX_TREE_DEPTH {
+            continue;
+        }
+        for child in children(pid) {
+            if visited.insert(child) {
+                queue.push_back((child, depth + 1));
+            }
+        }
+    }
+    report
+}
+
+/// Apply the settings of `config` to every thread of a process, returning the first error.
+fn apply_to_process(pid: Pid, config: &SchedulerConfig) -> Result<(), Error> {
+    let list = || threads_of(pid);
+    let mut reports = Vec::new();
+    if let Some(cpuset) = config.get_affinity() {
+        reports.push(apply_all_threads(list, |tid| set_affinity(tid, cpuset))?);
+    }
+    if let Some((policy, priority)) = config.get_policy() {
+        reports.push(apply_all_threads(list, |tid| set_policy(tid, policy, priority))?);
+    }
+    if let Some(nice) = config.get_nice() {
+        let set_nice = |tid: Tid| set_priority_for(Who::Process(tid.as_raw()), nice);
+        reports.push(apply_all_threads(list, set_nice)?);
+    }
+    for report in reports {
+        if let Some((_, error)) = report.failed.into_iter().next() {
+            return Err(error);
+        }
+        if report.applied.is_empty() && !report.vanished.is_empty() {
+            return Err(Error::from_raw_os_error(ESRCH));
+        }
+    }
+    Ok(())
+}
+
+/// List the children of every thread of a process, read from `/proc/<pid>/task/<tid>/children`.
+/// A process that exits meanwhile has no children.
+fn children_of(pid: Pid) -> Vec<Pid> {
+    let mut children = Vec::new();
+    for tid in threads_of(pid).unwrap_or_default() {
+        let file = format!("/proc/{}/task/{}/children", pid.as_raw(), tid.as_raw());
+        if let Ok(list) = fs::read_to_string(file) {
+            children.extend(parse_children(&list));
+        }
+    }
+    children
+}
+
+/// Parse a `children` file, the pids separated by spaces.
+fn parse_children(list: &str) -> Vec<Pid> {
+    list.split_whitespace().filter_map(|pid| pid.parse().ok()).map(Pid::from_raw).collect()
+}
+
+/// Map every process in `/proc` to its children, read from the `PPid` line of its status.
+fn scan_parents() -> Result<HashMap<Pid, Vec<Pid>>, Error> {
+    let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for entry in fs::read_dir("/proc")? {
+        let name = entry?.file_name();
+        let pid = match name.to_str().and_then(|name| name.parse().ok()) {
+            Some(pid) => Pid::from_raw(pid),
+            None => continue,
+        };
+        let status = format!("/proc/{}/status", pid.as_raw());
+        // Processes that exit while scanning are left out
+        if let Some(parent) = fs::read_to_string(status).ok().and_then(|s| parse_ppid(&s)) {
+            children.entry(parent).or_default().push(pid);
+        }
+    }
+    Ok(children)
+}
+
+/// Parse the `PPid` line of a `/proc/<pid>/status` file.
+fn parse_ppid(status: &str) -> Option<Pid> {
+    status.lines()
+        .find(|line| line.starts_with("PPid:"))
+        .and_then(|line| line["PPid:".len()..].trim().parse().ok())
+        .map(Pid::from_raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::{EPERM, ESRCH};
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use super::{apply_to_tree, parse_children, parse_ppid, scan_parents, walk_tree,
+                MAX_TREE_DEPTH};
+    use config::SchedulerConfig;
+    use error::{Error, ErrorKind};
+    use process::get_nice_all_threads;
+    use sched::Policy;
+    use target::Pid;
+
+    fn pids(raw: &[i32]) -> Vec<Pid> {
+        raw.iter().map(|&pid| Pid::from_raw(pid)).collect()
+    }
+
+    #[test]
+    fn test_parse_children() {
+        assert_eq!(pids(&[12, 345]), parse_children("12 345 \n"));
+        assert!(parse_children("").is_empty());
+        let status = "Name:\tsleep\nPid:\t12\nPPid:\t1\nTracerPid:\t0\n";
+        assert_eq!(Some(Pid::from_raw(1)), parse_ppid(status));
+        assert_eq!(None, parse_ppid("Name:\tsleep\n"));
+    }
+
+    #[test]
+    fn test_walk_tree() {
+        // 2 lists its parent as a child, like a reused pid could
+        let children = |pid: Pid| match pid.as_raw() {
+            1 => pids(&[2, 3]),
+            2 => pids(&[1, 4]),
+            3 => pids(&[5]),
+            _ => Vec::new(),
+        };
+        let report = walk_tree(Pid::from_raw(1), children, |pid| match pid.as_raw() {
+            3 => Err(Error::from_raw_os_error(ESRCH)),
+            4 => Err(Error::from_raw_os_error(EPERM)),
+            _ => Ok(()),
+        });
+        assert_eq!(pids(&[1, 2]), report.applied);
+        assert_eq!(pids(&[3]), report.vanished);
+        assert_eq!(pids(&[4]), report.failed.iter().map(|&(pid, _)| pid).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_walk_tree_depth() {
+        let children = |pid: Pid| pids(&[pid.as_raw() + 1]);
+        let report = walk_tree(Pid::from_raw(0), children, |_| Ok(()));
+        assert_eq!(MAX_TREE_DEPTH + 1, report.applied.len());
+    }
+
+    #[test]
+    fn test_scan_parents() {
+        let parents = scan_parents().unwrap();
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let parents_after = scan_parents().unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!parents.is_empty());
+        assert!(parents_after[&Pid::this()].contains(&Pid::from(&child)));
+    }
+
+    #[test]
+    fn test_apply_to_tree() {
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 2 & echo $!; wait"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap()).read_line(&mut line).unwrap();
+        let grandchild = Pid::from_raw(line.trim().parse().unwrap());
+        let config = SchedulerConfig::new().policy(Policy::Batch, 0).nice(6);
+        let report = apply_to_tree(Pid::from(&child), &config);
+        let nice_values = get_nice_all_threads(grandchild);
+        Command::new("kill").arg(grandchild.as_raw().to_string()).status().unwrap();
+        child.wait().unwrap();
+        let report = report.unwrap();
+        assert_eq!(vec![Pid::from(&child), grandchild], report.applied);
+        assert!(report.failed.is_empty() && report.vanished.is_empty());
+        assert_eq!(6, nice_values.unwrap()[0].1);
+    }
+
+    #[test]
+    fn test_apply_to_tree_errors() {
+        let config = SchedulerConfig::new().nice(1);
+        let error = apply_to_tree(Pid::from_raw(i32::MAX), &config).unwrap_err();
+        assert_eq!(ErrorKind::NoSuchProcess, error.kind());
+        let config = SchedulerConfig::new().policy(Policy::Other, 10);
+        match apply_to_tree(Pid::this(), &config) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! CPU usage of the system per CPU, read from `/proc/stat`, to place work on idle CPUs
+use std::fs;
+use std::io;
+use std::str::SplitWhitespace;
+use std::thread;
+use std::time::Duration;
+use cpuset::CpuSet;
+use error::Error;
+
+/// How long `pick_idle_cpu` samples the CPU usage.
+pub const PICK_IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time a CPU spent since boot, in clock ticks, see `cpu_usage_snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CpuUsage {
+    /// The CPU number
+    pub cpu: usize,
+    /// The time spent idle, including waiting for I/O
+    pub idle: u64,
+    /// The time spent in any state
+    pub total: u64,
+}
+
+impl CpuUsage {
+    /// Get the fraction of the time the CPU was idle since an earlier sample of the same CPU,
+    /// from 0 to 1.
+    ///
+    /// Returns `None` if no time passed between the samples, or the counters went back, e.g.
+    /// because the CPU went offline in between.
+    pub fn idle_fraction_since(&self, earlier: &CpuUsage) -> Option<f64> {
+        let total = self.total.checked_sub(earlier.total)?;
+        let idle = self.idle.checked_sub(earlier.idle)?;
+        match total {
+            0 => None,
+            total => Some(idle.min(total) as f64 / total as f64),
+        }
+    }
+}
+
+/// Get the time each online CPU spent idle and in total since boot, read from the `cpuN` lines
+/// of `/proc/stat`, ordered by CPU.
+///
+/// Offline CPUs are left out. Compare two snapshots with `CpuUsage::idle_fraction_since`.
+pub fn cpu_usage_snapshot() -> Result<Vec<CpuUsage>, Error> {
+    parse_proc_stat(&fs::read_to_string("/proc/stat")?)
+}
+
+/// Get the CPU of `allowed` that was idle the largest fraction of the time over the next 100
+/// milliseconds, e.g. to pin a new worker to it with `CpuSet::single`.
+///
+/// See `pick_idle_cpu_with_interval`.
+pub fn pick_idle_cpu(allowed: &CpuSet) -> Result<usize, Error> {
+    pick_idle_cpu_with_interval(allowed, PICK_IDLE_INTERVAL)
+}
+
+/// Get the CPU of `allowed` that was idle the largest fraction of the time over `interval`,
+/// sampled with `cpu_usage_snapshot` before and after sleeping for `interval`.
+///
+/// Ties go to the lowest CPU. If the counters did not advance in `interval`, the idle
+/// fractions since boot are compared instead. Returns an error if no CPU of `allowed` is online.
+pub fn pick_idle_cpu_with_interval(allowed: &CpuSet, interval: Duration) -> Result<usize, Error> {
+    let before = cpu_usage_snapshot()?;
+    thread::sleep(interval);
+    pick_idle(allowed, &before, &cpu_usage_snapshot()?)
+}
+
+fn pick_idle(allowed: &CpuSet, before: &[CpuUsage], after: &[CpuUsage]) -> Result<usize, Error> {
+    let since_boot = |usage: &CpuUsage| {
+        usage.idle_fraction_since(&CpuUsage { cpu: usage.cpu, idle: 0, total: 0 })
+    };
+    let mut best: Option<(usize, f64)> = None;
+    for usage in after.iter().filter(|usage| allowed.is_set(usage.cpu)) {
+        // A CPU that came online in between has no earlier sample
+        let earlier = before.iter().find(|earlier| earlier.cpu == usage.cpu);
+        let idle = earlier.and_then(|earlier| usage.idle_fraction_since(earlier))
+            .or_else(|| since_boot(usage))
+            .unwrap_or(0.0);
+        if best.map_or(true, |(_, best_idle)| idle > best_idle) {
+            best = Some((usage.cpu, idle));
+        }
+    }
+    match best {
+        Some((cpu, _)) => Ok(cpu),
+        None => Err(Error::InvalidArgument(format!("no CPU of {} is online", allowed))),
+    }
+}
+
+/// Parse the `cpuN` lines of a `/proc/stat` file. The columns are user, nice, system, idle,
+/// iowait, irq, softirq and steal, followed by the guest times that are included in user and
+/// nice.
+fn parse_proc_stat(stat: &str) -> Result<Vec<CpuUsage>, Error> {
+    let mut usage = Vec::new();
+    for line in stat.lines() {
+        let mut fields = line.split_whitespace();
+        let cpu = match fields.next().and_then(|name| name.strip_prefix("cpu")) {
+            Some(cpu) if !cpu.is_empty() => cpu,
+            _ => continue,
+        };
+        match parse_cpu_line(cpu, fields) {
+            Some(cpu_usage) => usage.push(cpu_usage),
+            None => {
+                let message = format!("invalid /proc/stat line {:?}", line);
+                return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidData, message)));
+            }
+        }
+    }
+    usage.sort_by_key(|usage| usage.cpu);
+    Ok(usage)
+}
+
+/// Parse the number and the times of a `cpuN` line.
+fn parse_cpu_line(cpu: &str, fields: SplitWhitespace) -> Option<CpuUsage> {
+    let times = fields.take(8).map(|time| time.parse().ok()).collect::<Option<Vec<u64>>>()?;
+    if times.len() < 4 {
+        return None;
+    }
+    Some(CpuUsage {
+        cpu: cpu.parse().ok()?,
+        idle: times[3] + times.get(4).unwrap_or(&0),
+        total: times.iter().sum(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{cpu_usage_snapshot, parse_proc_stat, pick_idle, pick_idle_cpu_with_interval,
+                CpuUsage};
+    use cpuset::CpuSet;
+    use error::{Error, ErrorKind};
+
+    const STAT: &str = "cpu  300 0 100 1500 100 0 0 0 0 0\n\
+                        cpu0 200 0 50 700 50 0 0 0 0 0\n\
+                        cpu2 100 0 50 800 50 0 0 0 0 0\n\
+                        intr 12345 0 0\nctxt 999\n";
+
+    #[test]
+    fn test_parse_proc_stat() {
+        assert_eq!(vec![CpuUsage { cpu: 0, idle: 750, total: 1000 },
+                        CpuUsage { cpu: 2, idle: 850, total: 1000 }],
+                   parse_proc_stat(STAT).unwrap());
+        // Kernels before 2.6.11 have no steal column
+        assert_eq!(vec![CpuUsage { cpu: 1, idle: 8, total: 10 }],
+                   parse_proc_stat("cpu1 1 0 1 7 1 0 0").unwrap());
+        let error = parse_proc_stat("cpu0 1 x 3 4\n").unwrap_err();
+        assert_eq!(ErrorKind::Other, error.kind());
+    }
+
+    #[test]
+    fn test_idle_fraction_since() {
+        let before = CpuUsage { cpu: 0, idle: 100, total: 200 };
+        let after = CpuUsage { cpu: 0, idle: 175, total: 300 };
+        assert_eq!(Some(0.75), after.idle_fraction_since(&before));
+        assert_eq!(None, before.idle_fraction_since(&before));
+        assert_eq!(None, before.idle_fraction_since(&after));
+    }
+
+    #[test]
+    fn test_pick_idle() {
+        let usage = |cpu, idle, total| CpuUsage { cpu, idle, total };
+        let before = vec![usage(0, 100, 200), usage(1, 100, 200), usage(2, 100, 200)];
+        let after = vec![usage(0, 150, 300), usage(1, 190, 300), usage(3, 95, 100)];
+        let all = CpuSet::all(4);
+        // CPU 2 went offline, CPU 3 came online and is compared since boot
+        assert_eq!(3, pick_idle(&all, &before, &after).unwrap());
+        let mut allowed = CpuSet::new(4);
+        allowed.set(0);
+        allowed.set(1);
+        assert_eq!(1, pick_idle(&allowed, &before, &after).unwrap());
+        // Without a delta the samples since boot decide
+        assert_eq!(0, pick_idle(&allowed, &before, &before).unwrap());
+        match pick_idle(&CpuSet::single(2), &before, &after) {
+            Err(Error::InvalidArgument(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_pick_idle_cpu() {
+        let snapshot = cpu_usage_snapshot().unwrap();
+        assert!(!snapshot.is_empty());
+        let online = CpuSet::online().unwrap();
+        let cpu = pick_idle_cpu_with_interval(&online, Duration::from_millis(20)).unwrap();
+        assert!(online.is_set(cpu));
+    }
+}